@@ -1,3 +1,4 @@
+#![cfg(not(any(feature = "criterion", feature = "divan")))]
 #![feature(test)]
 extern crate test;
 
@@ -5,3 +6,47 @@ extern crate test;
 async fn bench() {
     println!("hello world");
 }
+
+#[smol_potat::bench(iters = 100)]
+async fn bench_iters() {
+    smol::future::yield_now().await;
+}
+
+#[smol_potat::bench]
+async fn bench_generic<T: Default = u64>() {
+    std::hint::black_box(T::default());
+}
+
+#[smol_potat::bench]
+async fn bench_lifetimes<'a>()
+where
+    u64: Default,
+{
+    let name: &'a str = "bench";
+    std::hint::black_box((name, u64::default()));
+}
+
+#[smol_potat::bench(setup = vec![1u32; 1024])]
+async fn bench_setup(data: Vec<u32>) {
+    std::hint::black_box(data.iter().sum::<u32>());
+}
+
+#[smol_potat::bench(bytes = 4096)]
+async fn bench_bytes() {
+    std::hint::black_box(vec![0u8; 4096]);
+}
+
+#[smol_potat::bench(warmup = 50, local)]
+async fn bench_warmup() {
+    smol::future::yield_now().await;
+}
+
+#[smol_potat::bench(threads = 4)]
+async fn bench_threads() {
+    let tasks: Vec<_> = (0..4)
+        .map(|i| smol_potat::spawn(async move { std::hint::black_box(i * 2) }))
+        .collect();
+    for task in tasks {
+        task.await;
+    }
+}
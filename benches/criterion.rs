@@ -0,0 +1,48 @@
+use criterion::{criterion_group, criterion_main};
+
+#[smol_potat::bench]
+async fn bench() {
+    smol::future::yield_now().await;
+}
+
+#[smol_potat::bench(local)]
+async fn bench_local() {
+    let value = std::rc::Rc::new(1);
+    smol::future::yield_now().await;
+    std::hint::black_box(value);
+}
+
+#[smol_potat::bench(setup = vec![1u32; 1024])]
+async fn bench_setup(data: Vec<u32>) {
+    std::hint::black_box(data.iter().sum::<u32>());
+}
+
+#[smol_potat::bench(bytes = 4096, iters = 10)]
+async fn bench_bytes() {
+    std::hint::black_box(vec![0u8; 4096]);
+}
+
+#[smol_potat::bench(warmup = 50, setup = vec![1u32; 1024])]
+async fn bench_warmup(data: Vec<u32>) {
+    std::hint::black_box(data.iter().sum::<u32>());
+}
+
+#[smol_potat::bench]
+async fn bench_lifetimes<'a>()
+where
+    u64: Default,
+{
+    let name: &'a str = "bench";
+    std::hint::black_box((name, u64::default()));
+}
+
+criterion_group!(
+    benches,
+    bench,
+    bench_local,
+    bench_setup,
+    bench_bytes,
+    bench_warmup,
+    bench_lifetimes
+);
+criterion_main!(benches);
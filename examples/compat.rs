@@ -0,0 +1,6 @@
+#[smol_potat::main(compat, threads = 2)]
+async fn main() -> reqwest::Result<()> {
+    let response = reqwest::get("http://example.com").await?;
+    println!("{}", response.status());
+    Ok(())
+}
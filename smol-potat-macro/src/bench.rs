@@ -0,0 +1,326 @@
+//! The expansion of `#[smol_potat::bench]`, for libtest, criterion or divan.
+
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+use crate::opts::{Mode, Opts};
+use crate::util::{
+    internal_ident, monomorphize, normalize_unit, threads_tokens, with_crate_root, wrapper_generics,
+};
+
+/// Generates the benchmark for a single async function.
+pub(crate) fn expand_bench(opts: &Opts, mut input: syn::ItemFn) -> TokenStream {
+    normalize_unit(&mut input.sig.output);
+
+    let ret = &input.sig.output;
+    let name = &input.sig.ident;
+    let attrs = &input.attrs;
+
+    let crate_root = &opts.crate_root;
+
+    if let (Some(_), Some((_, span))) = (opts.local, &opts.threads) {
+        return TokenStream::from(quote_spanned! { *span=>
+            compile_error!("a local executor cannot have threads attribute");
+        });
+    }
+    if let Err(err) = opts.reject_unsupported(Mode::Bench) {
+        return err.to_compile_error().into();
+    }
+
+    if input.sig.asyncness.is_none() {
+        return TokenStream::from(quote_spanned! { input.span() =>
+            compile_error!("the async keyword is missing from the function declaration");
+        });
+    }
+
+    // The setup value is passed to the benchmark as its only parameter.
+    let param = match (&opts.setup, input.sig.inputs.first()) {
+        (None, None) => None,
+        (Some((setup, _)), Some(syn::FnArg::Typed(param))) if input.sig.inputs.len() == 1 => {
+            if !input.sig.generics.params.is_empty() {
+                return TokenStream::from(quote_spanned! { input.sig.generics.span() =>
+                    compile_error!("benchmarks with a setup cannot be generic");
+                });
+            }
+
+            Some((setup, &*param.pat, &*param.ty))
+        }
+        (Some((_, span)), None) => {
+            return TokenStream::from(quote_spanned! { *span=>
+                compile_error!("the benchmark must take the value returned by setup as its parameter");
+            });
+        }
+        (_, Some(_)) => {
+            return TokenStream::from(quote_spanned! { input.sig.inputs.span() =>
+                compile_error!("benchmarks cannot take parameters, except for the value returned by setup");
+            });
+        }
+    };
+
+    let body = match monomorphize(&input) {
+        Ok(body) => body,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    if cfg!(feature = "criterion") {
+        return criterion_bench(&input, opts, param, body);
+    }
+    if cfg!(feature = "divan") {
+        return divan_bench(&input, opts, param, body);
+    }
+
+    let body = match param {
+        Some((setup, pat, ty)) => quote! {
+            {
+                let #pat: #ty = #setup;
+                #body
+            }
+        },
+        None => body,
+    };
+
+    let body = match opts.iters {
+        Some((iters, _)) => quote! {
+            {
+                for _ in 0..#iters {
+                    #crate_root::std::hint::black_box(async #body.await);
+                }
+            }
+        },
+        None => quote!(#body),
+    };
+
+    let (lifetimes, where_clause) = wrapper_generics(&input.sig.generics);
+    let bencher = internal_ident("bencher");
+    let ex = internal_ident("ex");
+    // Every iteration of the bencher runs the body `iters` times.
+    let bytes = opts.bytes.map(|(bytes, _)| {
+        let bytes = bytes.saturating_mul(opts.iters.map_or(1, |(iters, _)| iters).into());
+        quote!(#bencher.bytes = #bytes;)
+    });
+    let (executor, run) = bench_executor(opts, &ex, &body);
+    let warmup = opts.warmup.map(|(warmup, _)| {
+        quote! {
+            for _ in 0..#warmup {
+                let _ = #run;
+            }
+        }
+    });
+    let result = quote! {
+        #[bench]
+        #(#attrs)*
+        fn #name #lifetimes(#bencher: &mut ::test::Bencher) #ret #where_clause {
+            #executor
+            #bytes
+            #warmup
+            let _ = #bencher.iter(|| #run);
+        }
+    };
+
+    with_crate_root(opts, result).into()
+}
+
+/// Returns the statement creating the executor a benchmark runs on, if it needs one, and the
+/// expression running the body on it.
+///
+/// With `threads`, the worker threads are started once, before the measurement, and stopped when
+/// the benchmark function returns.
+fn bench_executor(
+    opts: &Opts,
+    ex: &syn::Ident,
+    body: &proc_macro2::TokenStream,
+) -> (Option<proc_macro2::TokenStream>, proc_macro2::TokenStream) {
+    let crate_root = &opts.crate_root;
+    if opts.local.is_some() {
+        (
+            Some(quote!(let #ex = #crate_root::async_executor::LocalExecutor::new();)),
+            quote!(#crate_root::block_on(#ex.run(async #body))),
+        )
+    } else if let Some(threads) = &opts.threads {
+        let threads = threads_tokens(threads, crate_root);
+        (
+            Some(quote!(let #ex = #crate_root::__private::bench_workers(#threads);)),
+            quote!(#ex.block_on(async #body)),
+        )
+    } else {
+        (None, quote!(#crate_root::block_on(async #body)))
+    }
+}
+
+/// Generates a criterion benchmark, a function taking `&mut Criterion` that can be passed to
+/// `criterion_group!`.
+///
+/// Unlike with libtest, the setup can be left out of the measurement with `iter_batched`.
+fn criterion_bench(
+    input: &syn::ItemFn,
+    opts: &Opts,
+    param: Option<(&syn::Expr, &syn::Pat, &syn::Type)>,
+    body: proc_macro2::TokenStream,
+) -> TokenStream {
+    let vis = &input.vis;
+    let name = &input.sig.ident;
+    let attrs = &input.attrs;
+    let crate_root = &opts.crate_root;
+    let (lifetimes, where_clause) = wrapper_generics(&input.sig.generics);
+
+    if let (Some((_, span)), Some(_)) = (opts.iters, &opts.setup) {
+        return TokenStream::from(quote_spanned! { span=>
+            compile_error!("criterion benchmarks cannot have both iters and setup attributes");
+        });
+    }
+
+    let body = match opts.iters {
+        Some((iters, _)) => quote! {
+            {
+                for _ in 0..#iters {
+                    #crate_root::std::hint::black_box(async #body.await);
+                }
+            }
+        },
+        None => body,
+    };
+
+    let criterion = internal_ident("criterion");
+    let group = internal_ident("group");
+    let bencher = internal_ident("bencher");
+    let ex = internal_ident("ex");
+    let value = internal_ident("value");
+
+    let (executor, run) = bench_executor(opts, &ex, &body);
+
+    let warmup = opts.warmup.map(|(warmup, _)| {
+        let setup = param.map(|(setup, pat, ty)| quote!(let #pat: #ty = #setup;));
+        quote! {
+            for _ in 0..#warmup {
+                #setup
+                let _ = #run;
+            }
+        }
+    });
+
+    let routine = match param {
+        Some((setup, pat, ty)) => quote! {
+            #bencher.iter_batched(
+                || #setup,
+                |#value| {
+                    let #pat: #ty = #value;
+                    #run
+                },
+                #crate_root::criterion::BatchSize::SmallInput,
+            )
+        },
+        None => quote!(#bencher.iter(|| #run)),
+    };
+
+    // Throughput can only be set on a group, so a benchmark with `bytes` gets a group of its own.
+    let register = match opts.bytes {
+        Some((bytes, _)) => {
+            // Every iteration runs the body `iters` times.
+            let bytes = bytes.saturating_mul(opts.iters.map_or(1, |(iters, _)| iters).into());
+            quote! {
+                let mut #group = #criterion.benchmark_group(#crate_root::std::stringify!(#name));
+                #group.throughput(#crate_root::criterion::Throughput::Bytes(#bytes));
+                #group.bench_function("bytes", |#bencher| #routine);
+                #group.finish();
+            }
+        }
+        None => quote! {
+            #criterion.bench_function(#crate_root::std::stringify!(#name), |#bencher| #routine);
+        },
+    };
+
+    let result = quote! {
+        #(#attrs)*
+        #vis fn #name #lifetimes(#criterion: &mut #crate_root::criterion::Criterion) #where_clause {
+            #executor
+            #warmup
+            #register
+        }
+    };
+
+    with_crate_root(opts, result).into()
+}
+
+/// Generates a divan benchmark, registered with `#[divan::bench]` for `divan::main()` to run.
+///
+/// As with criterion, the setup can be left out of the measurement with `with_inputs`.
+fn divan_bench(
+    input: &syn::ItemFn,
+    opts: &Opts,
+    param: Option<(&syn::Expr, &syn::Pat, &syn::Type)>,
+    body: proc_macro2::TokenStream,
+) -> TokenStream {
+    let vis = &input.vis;
+    let name = &input.sig.ident;
+    let attrs = &input.attrs;
+    let crate_root = &opts.crate_root;
+    let (lifetimes, where_clause) = wrapper_generics(&input.sig.generics);
+
+    if let (Some((_, span)), Some(_)) = (opts.iters, &opts.setup) {
+        return TokenStream::from(quote_spanned! { span=>
+            compile_error!("divan benchmarks cannot have both iters and setup attributes");
+        });
+    }
+
+    let body = match opts.iters {
+        Some((iters, _)) => quote! {
+            {
+                for _ in 0..#iters {
+                    #crate_root::std::hint::black_box(async #body.await);
+                }
+            }
+        },
+        None => body,
+    };
+
+    let bencher = internal_ident("bencher");
+    let ex = internal_ident("ex");
+    let value = internal_ident("value");
+
+    let (executor, run) = bench_executor(opts, &ex, &body);
+
+    let warmup = opts.warmup.map(|(warmup, _)| {
+        let setup = param.map(|(setup, pat, ty)| quote!(let #pat: #ty = #setup;));
+        quote! {
+            for _ in 0..#warmup {
+                #setup
+                let _ = #run;
+            }
+        }
+    });
+
+    // Every iteration runs the body `iters` times.
+    let counter = opts.bytes.map(|(bytes, _)| {
+        let bytes = bytes.saturating_mul(opts.iters.map_or(1, |(iters, _)| iters).into());
+        quote!(.counter(#crate_root::divan::counter::BytesCount::new(#bytes)))
+    });
+
+    // The closures run on the current thread, so they don't need to be `Sync` in local mode.
+    let routine = match param {
+        Some((setup, pat, ty)) => quote! {
+            #bencher
+                #counter
+                .with_inputs(|| #setup)
+                .bench_local_values(|#value| {
+                    let #pat: #ty = #value;
+                    #run
+                });
+        },
+        None => quote! {
+            #bencher #counter.bench_local(|| #run);
+        },
+    };
+
+    let result = quote! {
+        #[#crate_root::divan::bench(crate = #crate_root::divan)]
+        #(#attrs)*
+        #vis fn #name #lifetimes(#bencher: #crate_root::divan::Bencher) #where_clause {
+            #executor
+            #warmup
+            #routine
+        }
+    };
+
+    with_crate_root(opts, result).into()
+}
@@ -0,0 +1,500 @@
+//! The expansion of `#[smol_potat::main]`.
+
+use proc_macro::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+use crate::opts::{Executor, Mode, Opts, Threads, DETECT_BLOCKING_MS};
+use crate::util::{
+    default_threads_tokens, internal_ident, normalize_unit, reactor_tokens, result_ok_type,
+    threads_tokens, with_crate_root, workers_tokens,
+};
+
+/// Generates the entry point for a single async main function.
+pub(crate) fn expand_main(opts: &Opts, mut input: syn::ItemFn) -> TokenStream {
+    normalize_unit(&mut input.sig.output);
+
+    let ret = &input.sig.output;
+    let name = &input.sig.ident;
+    let vis = &input.vis;
+    let body = &input.block;
+    let attrs = &input.attrs;
+
+    let crate_root = &opts.crate_root;
+
+    let in_main = |modes: &[Mode]| modes.iter().any(|mode| mode.is_main());
+    if let Err(err) = opts.reject_except(in_main, "the main function") {
+        return err.to_compile_error().into();
+    }
+
+    // Checked before the options that need another one, so that an option the mode doesn't
+    // support isn't first reported as missing what it needs.
+    let mode = if opts.block_on.is_some() {
+        Mode::BlockOn
+    } else if opts.bare.is_some() {
+        Mode::Bare
+    } else if let Some((Executor::Global, _)) = opts.executor {
+        Mode::GlobalExecutor
+    } else if opts.local.is_some() {
+        Mode::Local
+    } else if let Some((Executor::Local, _)) = opts.executor {
+        Mode::OwnExecutor
+    } else {
+        Mode::Main
+    };
+    if let Err(err) = opts.reject_unsupported(mode) {
+        return err.to_compile_error().into();
+    }
+
+    if let Some((_, span)) = opts.retries {
+        if result_ok_type(ret).is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("retries requires the main function to return a Result");
+            });
+        }
+    }
+
+    if let (None, Some((_, span))) = (opts.retries, opts.backoff_ms) {
+        return TokenStream::from(quote_spanned! { span=>
+            compile_error!("backoff_ms requires the retries attribute");
+        });
+    }
+
+    if let Some(span) = opts.report_errors {
+        if result_ok_type(ret).is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("report_errors requires the main function to return a `Result`");
+            });
+        }
+    }
+
+    if let Some(span) = opts.display_errors {
+        if result_ok_type(ret).is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("display_errors requires the main function to return a `Result`");
+            });
+        }
+    }
+
+    if let Some(span) = opts.exit_code {
+        let is_i32 = match ret {
+            syn::ReturnType::Type(_, ty) => {
+                matches!(&**ty, syn::Type::Path(path) if path.qself.is_none() && path.path.is_ident("i32"))
+            }
+            syn::ReturnType::Default => false,
+        };
+        if !is_i32 {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("exit_code requires the main function to return `i32`");
+            });
+        }
+    }
+
+    if let (None, Some((_, span))) = (opts.catch_signals, opts.shutdown_grace_ms) {
+        return TokenStream::from(quote_spanned! { span=>
+            compile_error!("shutdown_grace_ms requires the catch_signals attribute");
+        });
+    }
+
+    let entry = !matches!(opts.entry, Some((false, _)));
+    if entry && name != "main" {
+        return TokenStream::from(quote_spanned! { name.span() =>
+            compile_error!("only the main function can be tagged with #[smol::main]");
+        });
+    }
+
+    // With the `clap` feature, main can take its command line arguments as a parameter.
+    let cli_args = match input.sig.inputs.first() {
+        None => None,
+        Some(syn::FnArg::Typed(arg)) if cfg!(feature = "clap") && input.sig.inputs.len() == 1 => {
+            Some(arg)
+        }
+        Some(_) if cfg!(feature = "clap") => {
+            return TokenStream::from(quote_spanned! { input.sig.inputs.span() =>
+                compile_error!("the main function can only take a single parameter, the parsed command line arguments");
+            });
+        }
+        Some(_) => {
+            return TokenStream::from(quote_spanned! { input.sig.paren_token.span =>
+                compile_error!("the main function cannot take parameters");
+            });
+        }
+    };
+
+    if input.sig.asyncness.is_none() && opts.allow_sync.is_none() {
+        // The body is dropped so that any `.await` in it does not add errors of its own, but an
+        // empty function is left behind so that rustc does not also report a missing `main`.
+        let vis = &input.vis;
+        let name = &input.sig.ident;
+        return TokenStream::from(quote_spanned! { input.span() =>
+            compile_error!("the async keyword is missing from the function declaration");
+            #vis fn #name() {}
+        });
+    }
+
+    if let syn::ReturnType::Type(_, ty) = ret {
+        if let syn::Type::Never(_) = **ty {
+            return TokenStream::from(quote_spanned! { ty.span() =>
+                compile_error!("the async main function cannot return `!`, loop in its body and return `()` instead");
+            });
+        }
+    }
+
+    let reactor = reactor_tokens(&opts.reactor);
+
+    // The number of worker threads, computed once and passed to the body as well.
+    let threads_var = internal_ident("threads");
+    let mut worker_threads = None;
+
+    let block_on = if let Some((block_on, span)) = &opts.block_on {
+        quote_spanned!(*span=> #block_on)
+    } else if let Some(bare) = opts.bare {
+        if let Some(arg) = cli_args {
+            return TokenStream::from(quote_spanned! { arg.span() =>
+                compile_error!("a bare main function cannot take parameters");
+            });
+        }
+
+        quote_spanned!(bare=> #crate_root::block_on)
+    } else if let Some((Executor::Global, global)) = opts.executor {
+        quote_spanned!(global=> #crate_root::async_global_executor::block_on)
+    } else {
+        let builder = if opts.local.is_some() {
+            quote! {
+                #crate_root::Builder::new().local(true)#reactor
+            }
+        } else {
+            let threads = match &opts.threads {
+                Some(threads @ (Threads::Env, _)) => threads_tokens(threads, crate_root),
+                Some(threads) => {
+                    let threads = threads_tokens(threads, crate_root);
+                    quote!(#crate_root::__private::warn_env_threads(#threads))
+                }
+                None => {
+                    let threads = default_threads_tokens(crate_root);
+                    quote!(#crate_root::__private::warn_env_threads(#threads))
+                }
+            };
+            // The config file takes precedence, the other options are the fallback.
+            let threads = match &opts.threads_config {
+                Some((path, span)) => quote_spanned! { *span=>
+                    #crate_root::__private::config_threads(#path).unwrap_or_else(|| #threads)
+                },
+                None => threads,
+            };
+
+            let workers = workers_tokens(opts);
+            worker_threads = Some(threads);
+
+            match (&opts.expose_executor, &opts.executor) {
+                (Some(_), _) => quote! {
+                    #crate_root::Builder::new()
+                        .threads(#threads_var)
+                        #workers
+                        #reactor
+                        .executor(#crate_root::std::clone::Clone::clone(&executor))
+                },
+                // Without an executor, the builder creates one for this run.
+                (None, Some((Executor::Local, _))) => quote! {
+                    #crate_root::Builder::new()
+                        .threads(#threads_var)
+                        #workers
+                        #reactor
+                },
+                (None, _) => quote! {
+                    #crate_root::Builder::new()
+                        .threads(#threads_var)
+                        #workers
+                        #reactor
+                        .executor(#crate_root::__private::global_executor())
+                },
+            }
+        };
+
+        quote!(#builder.build_block_on)
+    };
+
+    // The inner function can't capture locals, so the executor and the command line arguments are
+    // passed to it as arguments.
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    let mut prelude = Vec::new();
+    if let Some((hook, span)) = &opts.panic_hook {
+        prelude.push(quote_spanned! { *span=>
+            #crate_root::std::panic::set_hook(#crate_root::std::boxed::Box::new(#hook));
+        });
+    }
+    if let Some((threads, span)) = opts.blocking_threads {
+        prelude.push(quote_spanned! { span=>
+            #crate_root::__private::set_blocking_threads(#threads);
+        });
+    }
+    if let Some(span) = opts.diagnostics {
+        prelude.push(quote_spanned! { span=>
+            #crate_root::__private::dump_tasks_on_signal();
+        });
+    }
+    if let Some((ms, span)) = opts.idle_poll_ms {
+        prelude.push(quote_spanned! { span=>
+            #crate_root::__private::set_idle_poll(#crate_root::std::time::Duration::from_millis(#ms));
+        });
+    }
+    if let Some((threshold, span)) = opts.detect_blocking {
+        let ms = threshold.unwrap_or(DETECT_BLOCKING_MS);
+        prelude.push(quote_spanned! { span=>
+            #crate_root::__private::detect_blocking(
+                #crate_root::std::time::Duration::from_millis(#ms),
+            );
+        });
+    }
+    if opts.expose_executor.is_some() {
+        params.push(quote!(executor: #crate_root::std::sync::Arc<#crate_root::Executor<'static>>));
+        args.push(quote!(executor));
+        prelude.push(quote! {
+            let executor = #crate_root::__private::global_executor();
+        });
+    }
+    if let Some(threads) = worker_threads {
+        params.push(quote! {
+            #[allow(unused_variables, non_snake_case)] SMOL_POTAT_THREADS: usize
+        });
+        args.push(quote!(#threads_var));
+        prelude.push(quote! {
+            let #threads_var: usize = #threads;
+        });
+    }
+    if let Some(arg) = cli_args {
+        let ty = &arg.ty;
+        params.push(quote!(#arg));
+        args.push(quote!(args));
+        prelude.push(quote_spanned! { ty.span()=>
+            let args = <#ty as #crate_root::clap::Parser>::parse();
+        });
+    }
+
+    // A synchronous main is called from within the future, so it runs on the runtime as an async
+    // main would.
+    let asyncness = &input.sig.asyncness;
+    let call = |args: proc_macro2::TokenStream| match asyncness {
+        Some(_) => quote!(__smol_potat_main(#args)),
+        None => quote!((async { __smol_potat_main(#args) })),
+    };
+
+    let future = match opts.retries {
+        Some((retries, _)) => {
+            // Every attempt gets its own copy of the arguments.
+            let attempts = retries + 1;
+            let backoff_ms = opts.backoff_ms.map_or(0, |(ms, _)| ms);
+            let attempt = internal_ident("attempt");
+            let attempt_future = call(quote!(#(#crate_root::std::clone::Clone::clone(&#args)),*));
+            quote! {
+                async {
+                    let mut #attempt: u32 = 1;
+                    loop {
+                        match #attempt_future.await {
+                            #crate_root::std::result::Result::Err(error) if #attempt < #attempts => {
+                                #crate_root::std::eprintln!(
+                                    "main failed on attempt {} of {}, retrying in {}ms: {:?}",
+                                    #attempt,
+                                    #attempts,
+                                    #backoff_ms,
+                                    error,
+                                );
+                                #crate_root::async_io::Timer::after(
+                                    #crate_root::std::time::Duration::from_millis(#backoff_ms),
+                                )
+                                .await;
+                                #attempt += 1;
+                            }
+                            result => break result,
+                        }
+                    }
+                }
+            }
+        }
+        None => call(quote!(#(#args),*)),
+    };
+
+    let future = match opts.compat {
+        Some(_) => quote!(#crate_root::async_compat::Compat::new(#future)),
+        None => future,
+    };
+
+    let future = match opts.detect_blocking {
+        Some(_) => quote!(#crate_root::__private::watch_blocking(#future)),
+        None => future,
+    };
+
+    let future = match opts.fair {
+        Some(_) => quote!(#crate_root::__private::fair(#future)),
+        None => future,
+    };
+
+    let future = match &opts.wrap {
+        Some((wrap, span)) => quote_spanned!(*span=> #wrap(#future)),
+        None => future,
+    };
+
+    // Joining runs before the signal handling, so a signal stops the wait as well.
+    let future = match opts.join_spawned {
+        Some(_) => {
+            let output = internal_ident("output");
+            quote! {
+                async {
+                    let #output = #future.await;
+                    #crate_root::__private::join_spawned().await;
+                    #output
+                }
+            }
+        }
+        None => future,
+    };
+
+    let grace = opts.shutdown_grace_ms.map_or(0, |(ms, _)| ms);
+    let grace = quote!(#crate_root::std::time::Duration::from_millis(#grace));
+    let future = match opts.catch_signals {
+        Some(_) => quote!(#crate_root::__private::until_signal(#future, #grace)),
+        None => future,
+    };
+
+    // The finalizer runs on the runtime too, after the body whatever its outcome.
+    let future = match &opts.finalizer {
+        Some((finalizer, span)) => {
+            let output = internal_ident("output");
+            let finalize = quote_spanned!(*span=> #finalizer());
+            quote! {
+                async {
+                    let #output = #future.await;
+                    let _ = #finalize.await;
+                    #output
+                }
+            }
+        }
+        None => future,
+    };
+
+    let run = if opts.catch_signals.is_some() {
+        quote! {
+            match #block_on(#future) {
+                #crate_root::std::option::Option::Some(output) => output,
+                #crate_root::std::option::Option::None => {
+                    #crate_root::__private::Interrupted::interrupted()
+                }
+            }
+        }
+    } else {
+        quote! {
+            #block_on(#future)
+        }
+    };
+
+    // The workers have been joined by now, so every task they ran is counted.
+    let run = match opts.task_metrics {
+        Some(_) => {
+            let output = internal_ident("output");
+            quote! {
+                {
+                    let #output = #run;
+                    #crate_root::__private::print_task_metrics();
+                    #output
+                }
+            }
+        }
+        None => run,
+    };
+
+    // The process exits with the returned code, so main itself returns nothing.
+    let (run, main_ret) = if opts.exit_code.is_some() {
+        (quote!(#crate_root::std::process::exit(#run)), quote!())
+    } else {
+        (run, quote!(#ret))
+    };
+
+    // The error is printed here, and main reports the failure with an exit code instead.
+    let (run, main_ret) = match opts.report_errors {
+        Some(_) => {
+            let run = quote! {
+                match #run {
+                    #crate_root::std::result::Result::Ok(output) => {
+                        #crate_root::std::process::Termination::report(output)
+                    }
+                    #crate_root::std::result::Result::Err(err) => {
+                        #crate_root::std::eprintln!(
+                            "Error: {}",
+                            #crate_root::__private::format_error(err),
+                        );
+                        #crate_root::std::process::ExitCode::FAILURE
+                    }
+                }
+            };
+            (run, quote!(-> #crate_root::std::process::ExitCode))
+        }
+        None => (run, main_ret),
+    };
+
+    // Only `Display` is required of the error, where returning the `Result` would need `Debug`.
+    let (run, main_ret) = match opts.display_errors {
+        Some(_) => {
+            let run = quote! {
+                match #run {
+                    #crate_root::std::result::Result::Ok(output) => {
+                        #crate_root::std::process::Termination::report(output)
+                    }
+                    #crate_root::std::result::Result::Err(err) => {
+                        #crate_root::std::eprintln!("Error: {}", err);
+                        #crate_root::std::process::ExitCode::FAILURE
+                    }
+                }
+            };
+            (run, quote!(-> #crate_root::std::process::ExitCode))
+        }
+        None => (run, main_ret),
+    };
+
+    let init_tracing = opts.tracing.map(|_| {
+        quote! {
+            let _ = #crate_root::tracing_subscriber::fmt::try_init();
+        }
+    });
+    let init_env_logger = opts.env_logger.map(|_| {
+        quote! {
+            let _ = #crate_root::env_logger::try_init();
+        }
+    });
+
+    // Conditional compilation, lints and docs are about the entry point itself, while anything
+    // else, like `#[tracing::instrument]`, is meant for the async function.
+    let (outer_attrs, inner_attrs): (Vec<_>, Vec<_>) = attrs.iter().partition(|attr| {
+        [
+            "cfg",
+            "cfg_attr",
+            "doc",
+            "allow",
+            "warn",
+            "deny",
+            "forbid",
+            "expect",
+            "no_mangle",
+            "export_name",
+        ]
+        .iter()
+        .any(|name| attr.path.is_ident(name))
+    });
+
+    let result = quote! {
+        #(#outer_attrs)*
+        #vis fn #name() #main_ret {
+            #(#inner_attrs)*
+            #asyncness fn __smol_potat_main(#(#params),*) #ret {
+                #body
+            }
+
+            #init_tracing
+            #init_env_logger
+            #(#prelude)*
+            #run
+        }
+    };
+
+    with_crate_root(opts, result).into()
+}
@@ -2,11 +2,16 @@
 #![deny(missing_debug_implementations, nonstandard_style)]
 #![recursion_limit = "512"]
 
+mod bench;
+mod entry;
+mod opts;
+mod test;
+mod util;
+
 use proc_macro::TokenStream;
-use proc_macro2::Span;
-use quote::{quote, quote_spanned};
-use syn::parse::{Parse, ParseStream};
-use syn::spanned::Spanned;
+use quote::quote_spanned;
+
+use crate::opts::Opts;
 
 /// Enables an async main function.
 ///
@@ -14,8 +19,9 @@ use syn::spanned::Spanned;
 ///
 /// ## Dynamic threads
 ///
-/// By default, this spawns as many threads as is in the `SMOL_THREADS` environment variable, or 1
-/// if it is not specified.
+/// The async main function is run on an [`Executor`] which is also driven by a number of worker
+/// threads. By default, this spawns a single worker thread. The workers are shut down once main
+/// returns.
 ///
 /// ```ignore
 /// #[smol_potat::main]
@@ -24,6 +30,8 @@ use syn::spanned::Spanned;
 /// }
 /// ```
 ///
+/// [`Executor`]: https://docs.rs/async-executor/latest/async_executor/struct.Executor.html
+///
 /// ## Automatic Threadpool
 ///
 /// Alternatively, `smol_potat::main` can used to automatically
@@ -48,272 +56,1435 @@ use syn::spanned::Spanned;
 /// }
 /// ```
 ///
-/// ## Set the crate root
+/// `worker_threads` is accepted as an alias of `threads`. A literal number of threads must be at
+/// most 4096.
 ///
-/// By default `smol-potat` will use `::smol_potat` as its crate root, but you can override this
-/// with the `crate` option:
+/// The body can read the number of worker threads from `SMOL_POTAT_THREADS`, a `usize` binding
+/// that is computed once at startup, for example to size a pool or a channel. It is available
+/// whenever main runs worker threads, so not with `local`, `bare` or the global executor:
 ///
 /// ```ignore
-/// use smol_potat as other_smol_potat;
+/// #[smol_potat::main(threads = "auto")]
+/// async fn main() {
+///     let (sender, receiver) = smol::channel::bounded::<u32>(SMOL_POTAT_THREADS * 2);
+/// }
+/// ```
+///
+/// An explicit `threads`, like `threads_fn`, always takes precedence over the `auto` feature,
+/// which only changes the default. Since a feature can be enabled by any crate in the dependency
+/// graph, the macro doesn't warn about the combination: a function asking for a number of threads
+/// gets exactly that many, whether `auto` is enabled or not.
 ///
-/// #[smol_potat::main(crate = "other_smol_potat")]
+/// Passing `threads = "auto"`, or `threads = 0`, uses the number of CPUs detected at runtime
+/// instead, regardless of whether the `auto` feature is enabled:
+///
+/// ```ignore
+/// #[smol_potat::main(threads = "auto")]
 /// async fn main() -> std::io::Result<()> {
 ///     Ok(())
 /// }
 /// ```
-#[proc_macro_attribute]
-pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let input = syn::parse_macro_input!(item as syn::ItemFn);
-    let opts = syn::parse_macro_input!(attr as Opts);
-
-    let ret = &input.sig.output;
-    let name = &input.sig.ident;
-    let body = &input.block;
-    let attrs = &input.attrs;
-
-    let crate_root = opts.crate_root;
-
-    if name != "main" {
-        return TokenStream::from(quote_spanned! { name.span() =>
-            compile_error!("only the main function can be tagged with #[smol::main]"),
-        });
-    }
-
-    if !input.sig.inputs.is_empty() {
-        return TokenStream::from(quote_spanned! { input.sig.paren_token.span =>
-            compile_error!("the main function cannot take parameters"),
-        });
-    }
-
-    if input.sig.asyncness.is_none() {
-        return TokenStream::from(quote_spanned! { input.span() =>
-            compile_error!("the async keyword is missing from the function declaration"),
-        });
-    }
-
-    let threads = match opts.threads {
-        Some((num, span)) => {
-            let num = num.to_string();
-            Some(quote_spanned!(span=> #num))
-        }
-        #[cfg(feature = "auto")]
-        None => Some(quote! {
-            #crate_root::std::string::ToString::to_string(
-                &#crate_root::std::cmp::max(#crate_root::num_cpus::get(), 1)
-            )
-        }),
-        #[cfg(not(feature = "auto"))]
-        None => None,
-    };
-
-    let set_threads = threads.map(|threads| {
-        quote! {
-            #crate_root::std::env::set_var(
-                "SMOL_THREADS",
-                #threads,
-            );
-        }
-    });
-
-    let result = quote! {
-        fn main() #ret {
-            #(#attrs)*
-            async fn main() #ret {
-                #body
-            }
-
-            #set_threads
-
-            #crate_root::async_io::block_on(main())
-        }
-    };
-
-    result.into()
-}
-
-/// Enables an async test function.
 ///
-/// # Examples
+/// `threads = "respect_env"` reads the number of threads from the `SMOL_THREADS` environment
+/// variable at runtime, falling back to the default if it isn't set, or with a warning if it isn't
+/// a positive number. Otherwise main ignores `SMOL_THREADS`, and prints a warning when it is set
+/// to a different number:
 ///
 /// ```ignore
-/// #[smol_potat::test]
-/// async fn my_test() -> std::io::Result<()> {
-///     assert_eq!(2 * 2, 4);
+/// #[smol_potat::main(threads = "respect_env")]
+/// async fn main() -> std::io::Result<()> {
 ///     Ok(())
 /// }
 /// ```
-#[proc_macro_attribute]
-pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let input = syn::parse_macro_input!(item as syn::ItemFn);
-    let opts = syn::parse_macro_input!(attr as Opts);
-
-    let ret = &input.sig.output;
-    let name = &input.sig.ident;
-    let body = &input.block;
-    let attrs = &input.attrs;
-
-    let crate_root = opts.crate_root;
-
-    if let Some((_, span)) = opts.threads {
-        return TokenStream::from(quote_spanned! { span=>
-            compile_error!("tests cannot have threads attribute"),
-        });
-    }
-    if !input.sig.inputs.is_empty() {
-        return TokenStream::from(quote_spanned! { input.span() =>
-            compile_error!("tests cannot take parameters"),
-        });
-    }
-    if input.sig.asyncness.is_none() {
-        return TokenStream::from(quote_spanned! { input.span() =>
-            compile_error!("the async keyword is missing from the function declaration"),
-        });
-    }
-
-    let result = quote! {
-        #[test]
-        #(#attrs)*
-        fn #name() #ret {
-            #crate_root::async_io::block_on(async { #body })
-        }
-    };
-
-    result.into()
-}
-
-/// Enables an async benchmark function.
 ///
-/// # Examples
+/// In a container, the number of CPUs is the number of cores of the host. `threads = "container"`
+/// limits it to the CPU quota of the cgroup instead, rounded up, as read from `/sys/fs/cgroup` at
+/// startup. Without a quota, or on platforms other than Linux, it is the same as `"auto"`:
 ///
 /// ```ignore
-/// #![feature(test)]
-/// extern crate test;
+/// #[smol_potat::main(threads = "container")]
+/// async fn main() -> std::io::Result<()> {
+///     Ok(())
+/// }
+/// ```
 ///
-/// #[smol_potat::bench]
-/// async fn bench() {
-///     println!("hello world");
+/// Any other expression is evaluated at runtime and converted to a `usize`, so the number of
+/// threads can come from a constant or a function call:
+///
+/// ```ignore
+/// const THREADS: usize = 4;
+///
+/// #[smol_potat::main(threads = THREADS)]
+/// async fn main() -> std::io::Result<()> {
+///     Ok(())
 /// }
 /// ```
-#[proc_macro_attribute]
-pub fn bench(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let input = syn::parse_macro_input!(item as syn::ItemFn);
-    let opts = syn::parse_macro_input!(attr as Opts);
-
-    let ret = &input.sig.output;
-    let name = &input.sig.ident;
-    let body = &input.block;
-    let attrs = &input.attrs;
-
-    let crate_root = opts.crate_root;
-
-    if let Some((_, span)) = opts.threads {
-        return TokenStream::from(quote_spanned! { span=>
-            compile_error!("benchmarks cannot have threads attribute"),
-        });
-    }
-    if !input.sig.inputs.is_empty() {
-        return TokenStream::from(quote_spanned! { input.span() =>
-            compile_error!("benchmarks cannot take parameters"),
-        });
-    }
-    if input.sig.asyncness.is_none() {
-        return TokenStream::from(quote_spanned! { input.span() =>
-            compile_error!("the async keyword is missing from the function declaration"),
-        });
-    }
-
-    let result = quote! {
-        #[bench]
-        #(#attrs)*
-        fn #name(b: &mut ::test::Bencher) #ret {
-            let _ = b.iter(|| {
-                #crate_root::async_io::block_on(async {
-                    #body
-                })
-            });
-        }
-    };
-
-    result.into()
-}
-
-struct Opts {
-    crate_root: syn::Path,
-    threads: Option<(u32, Span)>,
-}
-
-impl Parse for Opts {
-    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
-        let mut crate_root = None;
-        let mut threads = None;
-
-        loop {
-            if input.is_empty() {
-                break;
-            }
-
-            let name_value: syn::MetaNameValue = input.parse()?;
-            let ident = match name_value.path.get_ident() {
-                Some(ident) => ident,
-                None => {
-                    return Err(syn::Error::new_spanned(
-                        name_value.path,
-                        "Must be a single ident",
-                    ))
-                }
-            };
-            match &*ident.to_string().to_lowercase() {
-                "threads" => match &name_value.lit {
-                    syn::Lit::Int(expr) => {
-                        if threads.is_some() {
-                            return Err(syn::Error::new_spanned(
-                                name_value,
-                                "multiple threads argments",
-                            ));
-                        }
-
-                        let num = expr.base10_parse::<std::num::NonZeroU32>()?;
-                        threads = Some((num.get(), expr.span()));
-                    }
-                    _ => {
-                        return Err(syn::Error::new_spanned(
-                            name_value,
-                            "threads argument must be an integer",
-                        ))
-                    }
-                },
-                "crate" => match &name_value.lit {
-                    syn::Lit::Str(path) => {
-                        if crate_root.is_some() {
-                            return Err(syn::Error::new_spanned(
-                                name_value,
-                                "multiple crate arguments",
-                            ));
-                        }
-
-                        crate_root = Some(path.parse()?);
-                    }
-                    _ => {
-                        return Err(syn::Error::new_spanned(
-                            name_value,
-                            "crate argument must be a string",
-                        ))
-                    }
-                },
-                name => {
-                    return Err(syn::Error::new_spanned(
-                        name,
-                        "unknown attribute {}, expected `threads` or `crate`",
-                    ));
-                }
-            }
-
-            input.parse::<Option<syn::Token![,]>>()?;
-        }
-
-        Ok(Self {
-            crate_root: crate_root.unwrap_or_else(|| syn::parse2(quote!(::smol_potat)).unwrap()),
-            threads,
-        })
-    }
+///
+/// To decide at startup, for example from a configuration file, `threads_fn` takes a
+/// `fn() -> usize` that is called to get the number of threads. It cannot be combined with
+/// `threads`, and it takes precedence over the `auto` feature:
+///
+/// ```ignore
+/// fn configured_threads() -> usize {
+///     // read the number of threads from the configuration
+///     2
+/// }
+///
+/// #[smol_potat::main(threads_fn = configured_threads)]
+/// async fn main() -> std::io::Result<()> {
+///     Ok(())
+/// }
+/// ```
+///
+/// ## Threads from a config file
+///
+/// With the `config` feature, `threads_config` reads the number of worker threads from the
+/// `threads` field of a file at startup. The path is relative to the current directory, and the
+/// file is parsed as JSON if the path ends with `.json` and as TOML otherwise:
+///
+/// ```toml
+/// threads = 4
+/// ```
+///
+/// ```json
+/// {"threads": 4}
+/// ```
+///
+/// Other fields are ignored, so the file can be shared with the rest of the configuration. The
+/// file takes precedence over `threads` and `threads_fn`: if it doesn't exist or has no `threads`
+/// field, main falls back to those, or to the default. A file that cannot be parsed, or a
+/// `threads` that is not a positive integer, panics before any thread is started:
+///
+/// ```ignore
+/// #[smol_potat::main(threads_config = "runtime.toml", threads = 2)]
+/// async fn main() -> std::io::Result<()> {
+///     Ok(())
+/// }
+/// ```
+///
+/// ## Thread names
+///
+/// The worker threads can be given a name, which shows up in debuggers and panic messages. Each
+/// thread is named `{thread_name}-{index}`, starting at 0:
+///
+/// ```ignore
+/// #[smol_potat::main(thread_name = "worker", threads = 4)]
+/// async fn main() {
+///     // runs on threads named `worker-0` to `worker-3`
+/// }
+/// ```
+///
+/// ## Stack size
+///
+/// The stack size of the worker threads is set in bytes with `stack_size`:
+///
+/// ```ignore
+/// #[smol_potat::main(stack_size = 8388608)]
+/// async fn main() {
+///     // runs on workers with 8 MiB stacks
+/// }
+/// ```
+///
+/// ## Thread start hook
+///
+/// `on_thread_start` takes a function, or any expression implementing `Fn() + Send + Sync`,
+/// which is called at the start of every worker thread before it runs any task. This is the place
+/// to set up thread-local state such as metrics registries:
+///
+/// ```ignore
+/// fn init_worker() {
+///     // runs once on each of the 4 workers
+/// }
+///
+/// #[smol_potat::main(on_thread_start = init_worker, threads = 4)]
+/// async fn main() {}
+/// ```
+///
+/// ## Pinning threads
+///
+/// With the `core_affinity` feature, `pin_threads` pins each worker thread to a CPU core, going
+/// round-robin over the cores, so the OS doesn't migrate them between cores:
+///
+/// ```ignore
+/// #[smol_potat::main(pin_threads, threads = 4)]
+/// async fn main() {
+///     // each worker stays on its own core
+/// }
+/// ```
+///
+/// On platforms where the cores or the affinity of a thread can't be determined, the threads are
+/// left unpinned.
+///
+/// ## Blocking threads
+///
+/// Blocking work like `smol::unblock` runs on a separate thread pool, which grows up to 500
+/// threads by default. `blocking_threads` sets its limit before main starts:
+///
+/// ```ignore
+/// #[smol_potat::main(blocking_threads = 16)]
+/// async fn main() {
+///     let contents = smol::unblock(|| std::fs::read_to_string("config.toml")).await;
+/// }
+/// ```
+///
+/// The limit takes precedence over the `BLOCKING_MAX_THREADS` environment variable, which is
+/// still read when the option is not given.
+///
+/// ## Fairness
+///
+/// `async-executor` has no fairness settings to configure. A runner polls the future it blocks
+/// on, here the body of main, and whenever the body is pending, runs the tasks that are ready,
+/// yielding after 200 of them in a row.
+///
+/// With `fair`, every 32nd time the body is woken, it yields to the executor once more before it
+/// is polled, like `futures_lite::future::yield_now`, so tasks that became ready in the meantime
+/// run first. This helps a body that is woken again as soon as it is pending, like one polling a
+/// queue in a loop, to share the main thread with the tasks:
+///
+/// ```ignore
+/// #[smol_potat::main(fair)]
+/// async fn main() {
+///     loop {
+///         smol::future::yield_now().await;
+///         poll_queue();
+///     }
+/// }
+/// ```
+///
+/// It can't interrupt a body that doesn't return to the executor at all: awaiting futures that
+/// are already complete, for example in a loop, runs on without being pending, so the tasks still
+/// wait until the body awaits something that isn't ready.
+///
+/// ## Task metrics
+///
+/// With `task_metrics`, main prints how many tasks were spawned and how many of them completed
+/// to stderr once the runtime has shut down, along with the largest number of tasks that were
+/// pending at once:
+///
+/// ```ignore
+/// #[smol_potat::main(task_metrics)]
+/// async fn main() {
+///     let tasks: Vec<_> = (0..4).map(|i| smol_potat::spawn(async move { i })).collect();
+///     for task in tasks {
+///         task.await;
+///     }
+///     // prints "task metrics: 4 spawned, 4 completed, at most 4 at once"
+/// }
+/// ```
+///
+/// Only tasks spawned with [`smol_potat::spawn`] are counted, not those spawned directly onto an
+/// `Executor`, for example through `expose_executor`. A task that is cancelled before it
+/// completes counts as spawned but not completed.
+///
+/// ## Detecting blocking calls
+///
+/// A synchronous blocking call in async code, like `std::thread::sleep` or reading a file with
+/// `std::fs`, stalls the thread polling it and every task waiting for that thread. With
+/// `detect_blocking`, a watchdog thread warns on stderr about every poll that takes longer than a
+/// threshold, in milliseconds, 100 by default:
+///
+/// ```ignore
+/// #[smol_potat::main(detect_blocking = 50)]
+/// async fn main() {
+///     // warns that thread 'main' has been polling a task for 50ms without yielding
+///     std::thread::sleep(std::time::Duration::from_millis(200));
+/// }
+/// ```
+///
+/// This is a heuristic: only the polls of main's body and of the tasks spawned with
+/// [`smol_potat::spawn`] are watched, a poll doing a lot of work without blocking is reported as
+/// well, and a blocking call shorter than the threshold goes unnoticed. Each slow poll is reported
+/// once, with the name of the thread running it.
+///
+/// ## Idle wakeups
+///
+/// An idle runtime doesn't poll: its threads sleep until a task is woken, an I/O event arrives or
+/// a timer fires, and `async-io` doesn't let that sleep be tuned. For power-sensitive daemons,
+/// `idle_poll_ms` reduces timer wakeups instead, by rounding the deadline of every
+/// [`smol_potat::time::sleep`] up to the next multiple of that many milliseconds, so sleeps ending
+/// within the same interval wake the program once:
+///
+/// ```ignore
+/// #[smol_potat::main(idle_poll_ms = 100)]
+/// async fn main() {
+///     loop {
+///         // wakes on a 100ms boundary, between 1 and 1.1 seconds from now
+///         smol_potat::time::sleep(std::time::Duration::from_secs(1)).await;
+///     }
+/// }
+/// ```
+///
+/// Sleeps can end up to the interval late, never early. Tasks woken by something else than a
+/// sleep run as promptly as without the option, and `async_io::Timer` is not affected.
+///
+/// [`smol_potat::time::sleep`]: https://docs.rs/smol-potat/latest/smol_potat/time/fn.sleep.html
+///
+/// ## Task diagnostics
+///
+/// With the `diagnostics` feature, the tasks spawned with [`smol_potat::spawn`] are registered
+/// while they are pending, and [`smol_potat::dump_tasks`] returns the name and state of each of
+/// them. Tasks are named with [`smol_potat::spawn_named`], the others are `<unnamed>`. The
+/// `diagnostics` flag also prints them to stderr whenever the process receives `SIGQUIT`, for
+/// example with `Ctrl-\` in a terminal, to find out which tasks are stuck:
+///
+/// ```ignore
+/// #[smol_potat::main(diagnostics)]
+/// async fn main() {
+///     // `kill -QUIT <pid>` prints "task dump: 1 pending tasks" and "  <unnamed>: Waiting"
+///     smol_potat::spawn(smol::future::pending::<()>()).await;
+/// }
+/// ```
+///
+/// Tasks spawned directly onto an `Executor` are not registered. On platforms other than Unix,
+/// which have no `SIGQUIT`, the flag does nothing, but [`smol_potat::dump_tasks`] still works.
+/// The `SIGQUIT` handler is installed with `async-signal`, so neither does it without the
+/// `async-signal` feature of smol-potat, or with `ctrlc-integration`.
+///
+/// [`smol_potat::dump_tasks`]: https://docs.rs/smol-potat/latest/smol_potat/fn.dump_tasks.html
+/// [`smol_potat::spawn_named`]: https://docs.rs/smol-potat/latest/smol_potat/fn.spawn_named.html
+///
+/// ## Panics in tasks
+///
+/// When a task spawned onto the executor panics, the panic is reported and the worker keeps
+/// running other tasks; the panic resumes wherever the task is awaited. With `panic = "abort"`,
+/// any panic on the runtime's threads aborts the whole process instead, for servers that would
+/// rather restart than run degraded. `panic = "continue"` is the default:
+///
+/// ```ignore
+/// #[smol_potat::main(panic = "abort", threads = 4)]
+/// async fn main() {
+///     // a panic in any spawned task aborts the process
+/// }
+/// ```
+///
+/// With `panic = "propagate"`, the tasks keep running, but once the async main function returns
+/// the first panic is resumed on the main thread, so the process fails instead of exiting as if
+/// nothing happened. This includes panics that were resumed and caught elsewhere.
+///
+/// ## Panic hook
+///
+/// `panic_hook` takes a function, or any expression implementing
+/// `Fn(&PanicHookInfo) + Send + Sync`, which is installed with `std::panic::set_hook` once at
+/// startup, before the body runs. It replaces the default hook, so it decides how panics of the
+/// body and of every task are reported, for example with structured logging, and it can exit
+/// the process with a code of its own:
+///
+/// ```ignore
+/// fn report_panic(info: &std::panic::PanicHookInfo) {
+///     tracing::error!(%info, "panicked");
+///     std::process::exit(70);
+/// }
+///
+/// #[smol_potat::main(panic_hook = report_panic)]
+/// async fn main() {}
+/// ```
+///
+/// The `panic` option still applies, it runs after the hook.
+///
+/// ## Wrapping the body
+///
+/// `wrap` takes the path of a function that is called with the body's future before it is run,
+/// for wrappers applied to every entry point, like a metrics guard or a tracing span. It must
+/// take any future `F` and return a future, whose output becomes the result of main, so it
+/// usually passes `F::Output` through:
+///
+/// ```ignore
+/// async fn instrumented<F: std::future::Future>(future: F) -> F::Output {
+///     let start = std::time::Instant::now();
+///     let output = future.await;
+///     metrics::histogram!("main.duration").record(start.elapsed());
+///     output
+/// }
+///
+/// #[smol_potat::main(wrap = instrumented)]
+/// async fn main() {}
+/// ```
+///
+/// ## Finalizer
+///
+/// `finalizer` takes the path of an `async fn()` that runs after the body has completed, for
+/// work like flushing telemetry. It runs whether main returned `Ok` or `Err`, and after a
+/// termination signal with `catch_signals`, on the main thread while the worker threads are
+/// still running, so it can await tasks it spawns. Its result is ignored, but a panic in it
+/// propagates like one in the body:
+///
+/// ```ignore
+/// async fn flush() {
+///     telemetry::flush().await;
+/// }
+///
+/// #[smol_potat::main(finalizer = flush)]
+/// async fn main() -> std::io::Result<()> {
+///     Ok(())
+/// }
+/// ```
+///
+/// ## Exit codes
+///
+/// The async main function can return anything the synchronous one could, i.e. any type
+/// implementing [`Termination`], and the value is reported to the process as usual:
+///
+/// ```ignore
+/// use std::process::ExitCode;
+///
+/// #[smol_potat::main]
+/// async fn main() -> ExitCode {
+///     ExitCode::from(42)
+/// }
+/// ```
+///
+/// [`Termination`]: https://doc.rust-lang.org/std/process/trait.Termination.html
+///
+/// With the `exit_code` flag, main returns an `i32` instead, and the process exits with it
+/// through [`std::process::exit`] once the future completes. As `process::exit` doesn't unwind,
+/// destructors that are still pending at that point, like those of detached tasks or of values
+/// owned by the caller of the future, may not run:
+///
+/// ```ignore
+/// #[smol_potat::main(exit_code)]
+/// async fn main() -> i32 {
+///     3
+/// }
+/// ```
+///
+/// An `Err` returned from main is printed with `Debug`, as it would be for a synchronous main.
+/// With the `report_errors` flag, it is printed with `Display` instead, followed by the chain of
+/// its sources, and the process exits with [`ExitCode::FAILURE`]:
+///
+/// ```ignore
+/// #[smol_potat::main(report_errors)]
+/// async fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let config = std::fs::read_to_string("config.toml")?;
+///     // fails with "Error: No such file or directory (os error 2)"
+///     Ok(())
+/// }
+/// ```
+///
+/// The error type has to convert into `Box<dyn Error>`, like with `pretty_errors` on tests.
+///
+/// For error types that implement `Display` but not `Debug`, which a `Result` returned from main
+/// would require, `display_errors` prints the error with `Display` only, without its sources,
+/// and exits with [`ExitCode::FAILURE`] too. It cannot be combined with `report_errors`:
+///
+/// ```ignore
+/// struct UsageError(&'static str);
+///
+/// impl std::fmt::Display for UsageError {
+///     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+///         write!(f, "usage: {}", self.0)
+///     }
+/// }
+///
+/// #[smol_potat::main(display_errors)]
+/// async fn main() -> Result<(), UsageError> {
+///     Err(UsageError("app <config>"))
+/// }
+/// ```
+///
+/// [`ExitCode::FAILURE`]: https://doc.rust-lang.org/std/process/struct.ExitCode.html#associatedconstant.FAILURE
+///
+/// ## Retries
+///
+/// A main function returning a `Result` can be given a number of `retries`, for startup that
+/// depends on a service which may not be up yet. Whenever the body returns an `Err`, the error is
+/// printed and the body is run again after `backoff_ms` milliseconds, 0 by default. The error of
+/// the last attempt is returned from main:
+///
+/// ```ignore
+/// #[smol_potat::main(retries = 3, backoff_ms = 500)]
+/// async fn main() -> std::io::Result<()> {
+///     let stream = smol::net::TcpStream::connect("localhost:5432").await?;
+///     Ok(())
+/// }
+/// ```
+///
+/// Each attempt runs with a clone of main's parameters, so with the `clap` feature the arguments
+/// type must implement `Clone`.
+///
+/// ## Local executor
+///
+/// To drive `main` on a single-threaded [`LocalExecutor`], which allows futures that are not
+/// `Send`, add the `local` flag. It cannot be combined with `threads`:
+///
+/// ```ignore
+/// #[smol_potat::main(local)]
+/// async fn main() {
+///     let value = std::rc::Rc::new(1);
+///     println!("{}", value);
+/// }
+/// ```
+///
+/// [`LocalExecutor`]: https://docs.rs/async-executor/latest/async_executor/struct.LocalExecutor.html
+///
+/// ## Spawning tasks
+///
+/// The `expose_executor` flag makes the executor the worker threads are running available to the
+/// body as a variable named `executor`, of type `Arc<smol_potat::Executor<'static>>`, so tasks can
+/// be spawned onto it:
+///
+/// ```ignore
+/// #[smol_potat::main(expose_executor, threads = 4)]
+/// async fn main() {
+///     let task = executor.spawn(async { 1 + 2 });
+///     assert_eq!(task.await, 3);
+/// }
+/// ```
+///
+/// It cannot be combined with `local`. This is the executor [`smol_potat::spawn`] spawns onto;
+/// the handle is only needed to pass it to code that takes an `Executor`.
+///
+/// Tasks that are still running when main returns are dropped with the executor. With
+/// `join_spawned`, main waits for them to finish first, including the tasks they spawn in turn,
+/// before the worker threads are shut down:
+///
+/// ```ignore
+/// #[smol_potat::main(join_spawned)]
+/// async fn main() {
+///     smol_potat::spawn(flush_logs()).detach();
+///     // main returns once `flush_logs` has completed
+/// }
+/// ```
+///
+/// Only the tasks spawned with [`smol_potat::spawn`] are joined, not those spawned directly onto
+/// an `Executor`, and a task that never completes keeps main from returning. `join_spawned`
+/// cannot be combined with `local`, `bare`, `block_on` or `executor = "global"` or `"local"`,
+/// whose tasks don't run on this executor.
+///
+/// [`smol_potat::spawn`]: https://docs.rs/smol-potat/latest/smol_potat/fn.spawn.html
+///
+/// ## Global executor
+///
+/// By default, main runs on the executor that [`smol_potat::spawn`] spawns onto, driven by the
+/// worker threads it spawns. `executor = "local"` runs it on an executor of its own instead,
+/// created afresh for each run, which `smol_potat::spawn` doesn't reach, so it can't be combined
+/// with `expose_executor` or `join_spawned`. With the `global-executor` feature enabled,
+/// `executor = "global"` runs main with [`async_global_executor::block_on`] instead:
+///
+/// ```ignore
+/// #[smol_potat::main(executor = "global")]
+/// async fn main() {
+///     let task = async_global_executor::spawn(async { 1 + 2 });
+///     assert_eq!(task.await, 3);
+/// }
+/// ```
+///
+/// The global executor is shared by the whole process and keeps running between `block_on`
+/// calls, which suits libraries that spawn onto it themselves. Its threads are configured by
+/// `async-global-executor`, so the options configuring the worker threads can't be used, and
+/// tasks are spawned with `async_global_executor::spawn` rather than [`smol_potat::spawn`].
+///
+/// [`async_global_executor::block_on`]: https://docs.rs/async-global-executor/latest/async_global_executor/fn.block_on.html
+///
+/// ## Bare
+///
+/// For programs that manage their own executor, `bare` turns main into nothing but a call to
+/// [`smol_potat::block_on`] with its body. No threads are spawned, no executor is created and no
+/// environment variables are read:
+///
+/// ```ignore
+/// #[smol_potat::main(bare)]
+/// async fn main() {
+///     // runs on the main thread only, even with the `auto` feature
+/// }
+/// ```
+///
+/// `bare` disables all runtime configuration, so it cannot be combined with `threads`, `local`,
+/// `executor`, `reactor` or any of the options configuring worker threads, nor with
+/// `catch_signals`, `retries`, `report_errors`, `display_errors`, `compat`, `tracing`,
+/// `env_logger`, `detect_blocking`, `task_metrics`, `blocking_threads`, `idle_poll_ms` or
+/// `diagnostics`.
+///
+/// [`smol_potat::block_on`]: https://docs.rs/smol-potat/latest/smol_potat/fn.block_on.html
+///
+/// ## Custom block_on
+///
+/// To drive main with another runtime, `block_on` takes the path of a function used instead of
+/// the executor. It must take any future `F` and block the current thread until it completes,
+/// returning `F::Output`, like `fn block_on<F: Future>(future: F) -> F::Output`:
+///
+/// ```ignore
+/// fn block_on<F: std::future::Future>(future: F) -> F::Output {
+///     my_runtime::Runtime::new().block_on(future)
+/// }
+///
+/// #[smol_potat::main(block_on = block_on)]
+/// async fn main() {}
+/// ```
+///
+/// As with `bare`, no threads are spawned and no executor is created, so `block_on` cannot be
+/// combined with `bare`, `threads`, `local`, `executor`, `reactor` or any of the options
+/// configuring worker threads. The options wrapping the body, like `catch_signals` or `retries`,
+/// still apply.
+///
+/// ## Reactor
+///
+/// Timers and I/O events are processed by the `async-io` reactor, which is driven by whichever
+/// thread is blocked waiting for work. With `reactor = "dedicated"`, a thread is spawned that does
+/// nothing but drive the reactor, so events are picked up promptly even while every worker is
+/// busy. The default is `reactor = "shared"`:
+///
+/// ```ignore
+/// #[smol_potat::main(reactor = "dedicated", threads = 4)]
+/// async fn main() {
+///     smol::Timer::after(std::time::Duration::from_millis(10)).await;
+/// }
+/// ```
+///
+/// `async-io` has a single reactor per process, so this can't give main a reactor of its own;
+/// the dedicated thread drives the shared one. Tests accept the option too.
+///
+/// ## Catching signals
+///
+/// With the `catch_signals` flag, main is cancelled when the process receives `SIGINT` (Ctrl-C)
+/// or `SIGTERM`. The async main function is dropped, so destructors run, and main returns
+/// normally: `()`, `Ok(())` or `ExitCode::SUCCESS`, depending on its return type. An `i32` has no
+/// such value, so `catch_signals` can't be combined with `exit_code`. On platforms other than Unix
+/// only Ctrl-C is caught.
+///
+/// The signals are caught with `async-signal`, through the default `async-signal` feature of
+/// smol-potat. With the `ctrlc-integration` feature, the handler of the `ctrlc` crate is installed
+/// instead, for programs that already depend on it, and `async-signal` registers no handlers. It catches Ctrl-C and `SIGTERM` on Unix, and Ctrl-C as well as the console
+/// being closed on Windows. `ctrlc` allows a single handler per process, so the program must not
+/// install one of its own.
+///
+/// ```ignore
+/// #[smol_potat::main(catch_signals)]
+/// async fn main() -> std::io::Result<()> {
+///     loop {
+///         // serve requests until Ctrl-C is pressed
+///     }
+/// }
+/// ```
+///
+/// To shut down gracefully instead, `shutdown_grace_ms` gives main that many milliseconds after
+/// the signal before it is dropped. The signal is announced by [`smol_potat::shutdown_signal`],
+/// a future completing once it has been received, which the body and its tasks can wait on to
+/// stop taking new work. If main returns within the grace period, its output is used as usual;
+/// a second signal ends the grace period early:
+///
+/// ```ignore
+/// #[smol_potat::main(catch_signals, shutdown_grace_ms = 3000)]
+/// async fn main() {
+///     let server = smol_potat::spawn(serve());
+///     smol_potat::shutdown_signal().await;
+///     server.await; // has 3 seconds to finish
+/// }
+/// ```
+///
+/// [`smol_potat::shutdown_signal`]: https://docs.rs/smol-potat/latest/smol_potat/fn.shutdown_signal.html
+///
+/// ## Tracing
+///
+/// With the `tracing` feature enabled, the `tracing` flag installs the default
+/// [`tracing-subscriber`] formatter before main runs:
+///
+/// ```ignore
+/// #[smol_potat::main(tracing)]
+/// async fn main() {
+///     tracing::info!("starting up");
+/// }
+/// ```
+///
+/// [`tracing-subscriber`]: https://docs.rs/tracing-subscriber
+///
+/// For programs using the `log` crate instead, the `env_logger` feature and flag initialize
+/// [`env_logger`], configured by the `RUST_LOG` environment variable. Only one of them can be
+/// used, since both install a global logger:
+///
+/// ```ignore
+/// #[smol_potat::main(env_logger)]
+/// async fn main() {
+///     log::info!("starting up");
+/// }
+/// ```
+///
+/// [`env_logger`]: https://docs.rs/env_logger
+///
+/// ## Tokio compatibility
+///
+/// Libraries built on tokio, like `reqwest` or `hyper`, panic when they are not run inside a
+/// tokio context. With the `compat` feature enabled, the `compat` flag wraps main in
+/// [`async_compat::Compat`], which provides one. It can be combined with `threads`:
+///
+/// ```ignore
+/// #[smol_potat::main(compat, threads = 4)]
+/// async fn main() -> reqwest::Result<()> {
+///     let body = reqwest::get("http://example.com").await?.text().await?;
+///     println!("{}", body);
+///     Ok(())
+/// }
+/// ```
+///
+/// [`async_compat::Compat`]: https://docs.rs/async-compat/latest/async_compat/struct.Compat.html
+///
+/// ## Command line arguments
+///
+/// With the `clap` feature enabled, main can take a single parameter of a type implementing
+/// [`clap::Parser`]. The command line is parsed into it before main runs, and the process exits
+/// with clap's usage message if that fails:
+///
+/// ```ignore
+/// #[derive(clap::Parser)]
+/// struct Args {
+///     #[arg(long, default_value_t = 8080)]
+///     port: u16,
+/// }
+///
+/// #[smol_potat::main]
+/// async fn main(args: Args) {
+///     println!("listening on {}", args.port);
+/// }
+/// ```
+///
+/// [`clap::Parser`]: https://docs.rs/clap/latest/clap/trait.Parser.html
+///
+/// ## Attributes
+///
+/// `cfg`, `cfg_attr`, `doc`, lint, `no_mangle` and `export_name` attributes on the async main
+/// function are applied to the generated `fn main`, so `#[cfg(...)]` gates the entry point
+/// itself. Other attributes stay on the async function:
+///
+/// ```ignore
+/// #[smol_potat::main]
+/// #[cfg(feature = "server")]
+/// async fn main() {}
+/// ```
+///
+/// ## Custom entry points
+///
+/// With `entry = false`, the function doesn't have to be called `main`. It is turned into a
+/// synchronous function of the same name and visibility that runs the runtime, but no `fn main`
+/// is generated, so you are responsible for calling it, e.g. from an exported entry point:
+///
+/// ```ignore
+/// #[smol_potat::main(entry = false)]
+/// #[no_mangle]
+/// pub async fn start() {
+///     // runs when the host calls `start`
+/// }
+/// ```
+///
+/// ## Synchronous main
+///
+/// To move a large synchronous main to async code piece by piece, `allow_sync` accepts a main
+/// function without the `async` keyword. The runtime is set up as usual and the body runs on it
+/// unchanged, so it can spawn tasks and call [`smol_potat::block_on`] on the parts that are
+/// already async:
+///
+/// ```ignore
+/// #[smol_potat::main(allow_sync)]
+/// fn main() {
+///     let task = smol_potat::spawn(async { 1 + 2 });
+///     assert_eq!(smol_potat::block_on(task), 3);
+/// }
+/// ```
+///
+/// ## Optional runtime
+///
+/// For crates where async is optional, `cfg` takes the name of one of the crate's features and
+/// sets up the runtime only when it is enabled. Without the feature, main is generated as with
+/// `bare`: the body is blocked on with [`smol_potat::block_on`] on the main thread, and the
+/// options configuring the runtime, like `threads`, `local`, `thread_name` or `detect_blocking`,
+/// are ignored:
+///
+/// ```ignore
+/// #[smol_potat::main(cfg = "async-runtime", threads = 4)]
+/// async fn main() {
+///     // on 4 worker threads with `async-runtime`, on the main thread only without it
+/// }
+/// ```
+///
+/// Options that would change how main behaves without the runtime, like `catch_signals`,
+/// `retries` or `report_errors`, cannot be combined with `cfg`, and neither can `bare` or
+/// `expose_executor`. `SMOL_POTAT_THREADS` is only bound when the feature is enabled.
+///
+/// ## Set the crate root
+///
+/// By default `smol-potat` will use `::smol_potat` as its crate root, but you can override this
+/// with the `crate` option:
+///
+/// ```ignore
+/// use smol_potat as other_smol_potat;
+///
+/// #[smol_potat::main(crate = other_smol_potat)]
+/// async fn main() -> std::io::Result<()> {
+///     Ok(())
+/// }
+/// ```
+///
+/// The path can also be given as a string, `crate = "other_smol_potat"`, as in earlier versions.
+///
+/// The generated code refers to the dependencies smol-potat re-exports through this path, so it
+/// must lead to smol-potat itself or to a module re-exporting all of it with
+/// `pub use smol_potat::*;`. A module that only re-exports some of its items doesn't work, and a
+/// path that doesn't lead to smol-potat is reported as an unresolved import at the `crate`
+/// argument.
+#[proc_macro_attribute]
+pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::ItemFn);
+    let mut opts = syn::parse_macro_input!(attr as Opts);
+
+    let (feature, span) = match opts.cfg.take() {
+        Some(cfg) => cfg,
+        None => return entry::expand_main(&opts, input),
+    };
+
+    // Without the feature, main runs as a bare main, so only options that work without a runtime
+    // are kept.
+    let unsupported = [
+        (opts.catch_signals, "catch_signals"),
+        (opts.retries.map(|(_, span)| span), "retries"),
+        (opts.report_errors, "report_errors"),
+        (opts.display_errors, "display_errors"),
+        (opts.compat, "compat"),
+        (opts.tracing, "tracing"),
+        (opts.env_logger, "env_logger"),
+        (opts.expose_executor, "expose_executor"),
+        (opts.bare, "bare"),
+        (opts.block_on.as_ref().map(|(_, span)| *span), "block_on"),
+    ];
+    if let Some((Some(span), name)) = unsupported.iter().find(|(span, _)| span.is_some()) {
+        let message = format!("cfg cannot be combined with {}", name);
+        return TokenStream::from(quote_spanned! { *span=>
+            compile_error!(#message);
+        });
+    }
+
+    let mut runtime = input.clone();
+    runtime
+        .attrs
+        .push(syn::parse_quote_spanned!(span=> #[cfg(feature = #feature)]));
+    let mut fallback = input;
+    fallback
+        .attrs
+        .push(syn::parse_quote_spanned!(span=> #[cfg(not(feature = #feature))]));
+
+    let mut bare = opts.clone();
+    bare.bare = Some(span);
+    bare.threads = None;
+    bare.local = None;
+    bare.executor = None;
+    bare.reactor = None;
+    bare.thread_name = None;
+    bare.stack_size = None;
+    bare.panic = None;
+    bare.on_thread_start = None;
+    bare.pin_threads = None;
+    bare.detect_blocking = None;
+    bare.join_spawned = None;
+    bare.threads_config = None;
+    bare.task_metrics = None;
+    bare.blocking_threads = None;
+    bare.idle_poll_ms = None;
+    bare.diagnostics = None;
+
+    let mut result = entry::expand_main(&opts, runtime);
+    result.extend(entry::expand_main(&bare, fallback));
+    result
+}
+
+/// Enables an async test function.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[smol_potat::test]
+/// async fn my_test() -> std::io::Result<()> {
+///     assert_eq!(2 * 2, 4);
+///     Ok(())
+/// }
+/// ```
+///
+/// The generated test returns whatever the async function returns, so any type implementing
+/// [`Termination`] works as it does for a synchronous test: `()`, `ExitCode`, or a `Result` whose
+/// error implements `Debug`, which fails the test with the error printed.
+///
+/// [`Termination`]: https://doc.rust-lang.org/std/process/trait.Termination.html
+///
+/// ## Threads
+///
+/// By default a test runs on the test thread only. To exercise concurrent code, the test can be
+/// run on an executor with worker threads, just like `main`:
+///
+/// ```ignore
+/// #[smol_potat::test(threads = 4)]
+/// async fn my_test() {
+///     // runs on an executor driven by 4 worker threads
+/// }
+/// ```
+///
+/// To run the same test with several thread counts, pass them to `matrix_threads`. This
+/// generates one test per count, named after the function with a `_threads_N` suffix:
+///
+/// ```ignore
+/// #[smol_potat::test(matrix_threads = [1, 4])]
+/// async fn my_test() {
+///     // runs as `my_test_threads_1` and `my_test_threads_4`
+/// }
+/// ```
+///
+/// `matrix_threads` cannot be combined with `threads` or `local`.
+///
+/// ## Cases
+///
+/// For table-driven tests, `cases` takes an array of expressions and generates one test per
+/// element, named after the function with a `_case_N` suffix where `N` is the index of the case.
+/// The case is bound to `case` in the body:
+///
+/// ```ignore
+/// #[smol_potat::test(cases = [(1, 2), (3, 4)])]
+/// async fn my_test() {
+///     // runs as `my_test_case_0` with `case == (1, 2)` and `my_test_case_1` with `case == (3, 4)`
+///     let (a, b) = case;
+///     assert_eq!(a + 1, b);
+/// }
+/// ```
+///
+/// With `matrix_threads` as well, every case is run with every thread count, as
+/// `my_test_case_0_threads_1` and so on.
+///
+/// As with `main`, `thread_name`, `stack_size`, `panic`, `on_thread_start` and `pin_threads`
+/// configure the worker threads of a test that has `threads` or `matrix_threads`.
+///
+/// A test with worker threads and `#[should_panic]` resumes the first panic of the tasks it
+/// spawned with [`smol_potat::spawn`] once the body returns, so a panic in a task fails the test
+/// even if the task is never awaited, and `#[should_panic]` sees it:
+///
+/// ```ignore
+/// #[smol_potat::test(threads = 2)]
+/// #[should_panic(expected = "boom")]
+/// async fn my_test() {
+///     let (sender, receiver) = smol::channel::bounded::<()>(1);
+///     smol_potat::spawn(async move {
+///         let _sender = sender;
+///         panic!("boom");
+///     })
+///     .detach();
+///     // the channel closes once the task has panicked and dropped its sender
+///     let _ = receiver.recv().await;
+/// }
+/// ```
+///
+/// The tasks spawned by a task of the test count as its own, but not the tasks of other tests,
+/// even though tests with worker threads share one executor. An explicit `panic` option replaces
+/// this: `panic = "continue"` turns it off, and `panic = "propagate"` resumes the first panic on
+/// the test's worker threads, as for `main`, whichever test spawned the task.
+///
+/// ## Limiting concurrency
+///
+/// The macro can't see the tasks a test spawns, but `max_concurrency` gives the body a semaphore
+/// with that many permits, bound to `semaphore` as an `Arc<smol::lock::Semaphore>`. Tasks that
+/// hold a permit while they work are then limited to that many at a time:
+///
+/// ```ignore
+/// #[smol_potat::test(threads = 4, max_concurrency = 8)]
+/// async fn my_test() {
+///     let tasks: Vec<_> = (0..100)
+///         .map(|_| {
+///             let semaphore = semaphore.clone();
+///             smol_potat::spawn(async move {
+///                 let _permit = semaphore.acquire().await;
+///                 // at most 8 tasks get here at once
+///             })
+///         })
+///         .collect();
+///     for task in tasks {
+///         task.await;
+///     }
+/// }
+/// ```
+///
+/// To catch runaway spawning, `max_tasks` limits how many tasks a test may spawn in total:
+/// [`smol_potat::spawn`] panics when the test, or one of the tasks it spawned, goes over the
+/// limit:
+///
+/// ```ignore
+/// #[smol_potat::test(threads = 4, max_tasks = 1000)]
+/// async fn my_test() {
+///     // panics if this spawns more than 1000 tasks, directly or from its tasks
+///     crawl("https://example.com").await;
+/// }
+/// ```
+///
+/// Only the tasks spawned with [`smol_potat::spawn`] count, not those spawned directly onto an
+/// `Executor`, and neither do the tasks they spawn.
+///
+/// [`smol_potat::spawn`]: https://docs.rs/smol-potat/latest/smol_potat/fn.spawn.html
+///
+/// ## Generic tests
+///
+/// A test can be generic as long as every type and const parameter has a default, which is the
+/// type the test is run with:
+///
+/// ```ignore
+/// #[smol_potat::test]
+/// async fn roundtrip<T: Codec = Json>() {
+///     // runs with `T = Json`
+/// }
+/// ```
+///
+/// Lifetime parameters and the `where` clause are kept, and the lifetimes can be named in the
+/// return type.
+///
+/// ## Property-based tests
+///
+/// With the `proptest` feature, `proptest` turns the test into a property test: the function
+/// takes its inputs as parameters, and a proptest runner generates 256 cases, or the number
+/// given with `proptest(cases = N)`, blocking on a new runtime for each of them. An input is
+/// generated by `any` of its type, which has to implement `Arbitrary`, unless a strategy is given
+/// with `#[strategy(..)]`:
+///
+/// ```ignore
+/// #[smol_potat::test(proptest(cases = 50))]
+/// async fn roundtrip(#[strategy(0u32..1000)] id: u32, name: String) {
+///     let stored = store(id, &name).await;
+///     assert_eq!(load(id).await, stored);
+/// }
+/// ```
+///
+/// The test can return `()`, `ExitCode` or a `Result` whose error implements `Debug`, and a case
+/// fails if it panics or doesn't succeed. The failing case is then shrunk, and the test panics
+/// with the minimal failing input, which proptest records in a `.proptest-regressions` file next
+/// to the test as usual. Each input is a pattern with a type, as in any function, but the test
+/// cannot take `self` or be generic. The other options apply to every case, so `timeout` is a
+/// limit per case, and `threads` runs every case on worker threads; `proptest` cannot be combined
+/// with `cases`, `retries` or `setup`.
+///
+/// ## Returning a future
+///
+/// Instead of an async function, a test can be a plain function returning an
+/// `impl Future<Output = T>`. The future is awaited as if it was the body of an async function
+/// returning `T`, which is handy when the future is built by a helper:
+///
+/// ```ignore
+/// #[smol_potat::test]
+/// fn my_test() -> impl std::future::Future<Output = ()> {
+///     async {
+///         assert_eq!(2 * 2, 4);
+///     }
+/// }
+/// ```
+///
+/// ## Fixtures
+///
+/// `setup` and `teardown` name async functions run around the test. The value returned by
+/// `setup` is passed to the test as its only parameter, whose pattern is the name it is bound to
+/// in the body:
+///
+/// ```ignore
+/// async fn start_database() -> Database {
+///     Database::start().await
+/// }
+///
+/// async fn stop_database() {
+///     Database::stop().await;
+/// }
+///
+/// #[smol_potat::test(setup = start_database, teardown = stop_database)]
+/// async fn my_test(db: Database) {
+///     db.query("SELECT 1").await;
+/// }
+/// ```
+///
+/// The teardown is run by a guard created once the setup has returned, so it also runs when the
+/// test panics, returns early or times out. It is run with [`smol_potat::block_on`] as the guard
+/// is dropped, and a teardown that panics while the test is already panicking aborts the process.
+///
+/// [`smol_potat::block_on`]: https://docs.rs/smol-potat/latest/smol_potat/fn.block_on.html
+///
+/// ## Local executor
+///
+/// Like `main`, a test can be run on a single-threaded `LocalExecutor` with the `local` flag:
+///
+/// ```ignore
+/// #[smol_potat::test(local)]
+/// async fn my_test() {
+///     let value = std::rc::Rc::new(1);
+///     assert_eq!(*value, 1);
+/// }
+/// ```
+///
+/// ## Shared executor
+///
+/// A test that runs in several phases, each blocked on in turn, can share one executor between
+/// them with `shared_executor`. The body then starts with `runtime`, a [`smol_potat::Runtime`]
+/// without worker threads whose `block_on` runs a phase, and `executor`, its
+/// `Arc<Executor<'static>>`. Tasks spawned onto `executor` in one phase keep running in the next:
+///
+/// ```ignore
+/// #[smol_potat::test(shared_executor)]
+/// async fn my_test() {
+///     runtime.block_on(async { executor.spawn(serve()).detach() });
+///     runtime.block_on(async { assert!(connect().await.is_ok()) });
+/// }
+/// ```
+///
+/// [`smol_potat::Runtime`]: https://docs.rs/smol-potat/latest/smol_potat/struct.Runtime.html
+///
+/// ## Paused time
+///
+/// With `start_paused`, the test runs with a paused clock: [`smol_potat::time::sleep`] completes
+/// as soon as the test has nothing else to do, and virtual time jumps to its deadline. Timer-heavy
+/// tests then run instantly and deterministically:
+///
+/// ```ignore
+/// #[smol_potat::test(start_paused)]
+/// async fn my_test() {
+///     let start = smol_potat::time::now();
+///     smol_potat::time::sleep(Duration::from_secs(60)).await;
+///     assert_eq!(smol_potat::time::now() - start, Duration::from_secs(60));
+/// }
+/// ```
+///
+/// Only the `smol_potat::time` clock is paused, `async_io::Timer` still waits in real time. The
+/// clock doesn't move while the test also waits on anything else, like I/O or a channel, even
+/// when that is raced against a sleep, so a timeout only fires once the other work has woken the
+/// test. It cannot be combined with `threads` or `local`.
+///
+/// [`smol_potat::time::sleep`]: https://docs.rs/smol-potat/latest/smol_potat/time/fn.sleep.html
+///
+/// ## Seeding randomness
+///
+/// With `seed`, the thread-local generator of `fastrand` is seeded before the body runs, so a
+/// test drawing random values with `fastrand` sees the same ones on every run. If the test
+/// panics, the seed is printed along with the failure, so a seed found by changing it can be
+/// pinned in the test:
+///
+/// ```ignore
+/// #[smol_potat::test(seed = 12345)]
+/// async fn my_test() {
+///     let port = fastrand::u16(1024..);
+///     // the same port on every run
+/// }
+/// ```
+///
+/// Only the thread polling the body is seeded, not the worker threads running the tasks it
+/// spawns. The seed is for `fastrand` 2, which smol-potat depends on; other versions of the
+/// crate have a generator of their own.
+///
+/// ## Isolation
+///
+/// libtest may run several tests on the same thread, so thread-local state set by one test can
+/// be seen by the next. With `isolated`, the test runs on a thread of its own that is joined
+/// when it is done, so its thread-locals always start out fresh. Panics are resumed on the test
+/// thread, and the test's output has to be `Send`:
+///
+/// ```ignore
+/// #[smol_potat::test(isolated)]
+/// async fn my_test() {
+///     // thread-locals are in their initial state here
+/// }
+/// ```
+///
+/// ## Timeout
+///
+/// A test can be given a timeout in milliseconds. If the test has not completed by then, it is
+/// cancelled, dropping its future so destructors run, and then panics with a message naming the
+/// test:
+///
+/// ```ignore
+/// #[smol_potat::test(timeout = 5000)]
+/// async fn my_test() {
+///     // fails with "test 'my_test' timed out after 5000ms" if this hangs
+/// }
+/// ```
+///
+/// Tests without a `timeout` attribute use the number of milliseconds in the
+/// `SMOL_POTAT_TEST_TIMEOUT` environment variable instead, which is read when the test runs. A value
+/// that is not a positive integer is ignored, as if the variable was not set.
+///
+/// ## Deadlock detection
+///
+/// A test with worker threads can fail fast when it deadlocks, rather than waiting for its
+/// timeout. With `deadlock_detect`, the test panics once neither its own future nor any of the
+/// tasks it spawned with `smol_potat::spawn` has made progress for one second, or the number of
+/// milliseconds given, reporting how many of those tasks are still pending:
+///
+/// ```ignore
+/// #[smol_potat::test(threads = 2, deadlock_detect = 5000)]
+/// async fn my_test() {
+///     // fails with "test 'my_test' deadlocked: no progress for 5000ms, with 2 spawned tasks
+///     // pending" if the tasks end up waiting on each other
+/// }
+/// ```
+///
+/// This is a heuristic. A test that legitimately waits for longer than the window, like on a long
+/// sleep or a slow server, is reported as deadlocked too, so the window should be longer than
+/// its longest wait. Only the tasks spawned by the test, or by those tasks, count as its
+/// progress; the tasks of other tests running at the same time don't.
+///
+/// ## Tracing
+///
+/// The `tracing` flag is available on tests too, where the subscriber writes to the captured test
+/// output instead:
+///
+/// ```ignore
+/// #[smol_potat::test(tracing)]
+/// async fn my_test() {
+///     tracing::info!("only shown if the test fails");
+/// }
+/// ```
+///
+/// Likewise the `env_logger` flag initializes `env_logger` in test mode.
+///
+/// ## Capturing logs
+///
+/// To check what a test logs, the `capture_logs` flag of the `log` feature records every line
+/// logged through the `log` facade while the test runs. The lines are bound to `logs` in the body,
+/// a [`smol_potat::logs::CapturedLogs`]:
+///
+/// ```ignore
+/// #[smol_potat::test(capture_logs)]
+/// async fn my_test() {
+///     log::warn!("disk almost full");
+///     assert!(logs.contains("disk almost full"));
+///     assert_eq!(logs.lines(), ["WARN disk almost full"]);
+/// }
+/// ```
+///
+/// Captures are per test: a line is recorded for the test whose future is being polled when it
+/// is logged, so tests running in parallel don't see each other's lines. Lines logged by tasks the
+/// test spawns onto worker threads are not captured. The capturing logger is installed as the
+/// logger of the process, so `capture_logs` cannot be combined with `tracing` or `env_logger`.
+///
+/// [`smol_potat::logs::CapturedLogs`]: https://docs.rs/smol-potat/latest/smol_potat/logs/struct.CapturedLogs.html
+///
+/// ## Tokio compatibility
+///
+/// Tests can use the `compat` flag as well, to run code that needs a tokio context.
+///
+/// ## Pretty errors
+///
+/// When a test returns an `Err`, libtest prints its `Debug` representation. With the
+/// `pretty_errors` flag, the test instead fails with the error's `Display` representation followed
+/// by the chain of its sources:
+///
+/// ```ignore
+/// #[smol_potat::test(pretty_errors)]
+/// async fn my_test() -> Result<(), Box<dyn std::error::Error>> {
+///     Err("something went wrong".into())
+/// }
+/// ```
+///
+/// The error type has to convert into `Box<dyn Error>`, which is the case for every type
+/// implementing `Error` as well as `String` and `anyhow::Error`.
+///
+/// With the `anyhow` feature, the `anyhow` flag is meant for tests returning `anyhow::Result`. An
+/// `Err` is printed to stderr with `{:?}`, which includes its context and its backtrace if one
+/// was captured, and the test then fails with the error and its context on one line:
+///
+/// ```ignore
+/// #[smol_potat::test(anyhow)]
+/// async fn my_test() -> anyhow::Result<()> {
+///     let config = read_config().context("failed to read config.toml")?;
+///     Ok(())
+/// }
+/// ```
+///
+/// The error type has to convert into `anyhow::Error`. `anyhow` cannot be combined with
+/// `pretty_errors`.
+///
+/// ## Attributes
+///
+/// The generated test gets `#[test]` first, then `#[ignore]` if the `ignore` option is given, then
+/// every other attribute of the async function in its original order, whether it was written
+/// above or below `#[smol_potat::test]`. Nothing is dropped except a `#[test]` written by hand,
+/// which would otherwise be duplicated:
+///
+/// ```ignore
+/// #[smol_potat::test]
+/// #[should_panic(expected = "boom")]
+/// async fn my_test() {
+///     panic!("boom");
+/// }
+/// ```
+///
+/// ## Ignoring tests
+///
+/// `ignore` marks the test with `#[ignore]`, and `ignore = "reason"` with `#[ignore = "reason"]`,
+/// so libtest skips it and prints the reason:
+///
+/// ```ignore
+/// #[smol_potat::test(ignore = "needs a database")]
+/// async fn my_test() {}
+/// ```
+///
+/// ## Retries
+///
+/// A flaky test can be retried a number of times. The test only fails if every attempt panics or
+/// returns an `Err`, in which case the failure of the last attempt is reported unchanged:
+///
+/// ```ignore
+/// #[smol_potat::test(retries = 3)]
+/// async fn my_test() -> std::io::Result<()> {
+///     // runs up to 4 times
+///     Ok(())
+/// }
+/// ```
+///
+/// Retries are supported for tests returning `()`, `ExitCode` or a `Result`.
+#[proc_macro_attribute]
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::ItemFn);
+    let mut opts = syn::parse_macro_input!(attr as Opts);
+
+    // Each case becomes a test of its own, named after its index, with the case bound at the
+    // start of the body.
+    match opts.cases.take() {
+        Some((cases, _)) => cases
+            .into_iter()
+            .enumerate()
+            .map(|(index, case)| {
+                let mut input = input.clone();
+                input.sig.ident = quote::format_ident!("{}_case_{}", input.sig.ident, index);
+                input
+                    .block
+                    .stmts
+                    .insert(0, syn::parse_quote!(let case = #case;));
+                test::expand_test(&opts, input)
+            })
+            .collect(),
+        None => test::expand_test(&opts, input),
+    }
+}
+
+/// Enables an async benchmark function.
+///
+/// # Examples
+///
+/// ```ignore
+/// #![feature(test)]
+/// extern crate test;
+///
+/// #[smol_potat::bench]
+/// async fn bench() {
+///     println!("hello world");
+/// }
+/// ```
+///
+/// ## Iterations
+///
+/// For cheap futures, the cost of `block_on` itself can dominate the measurement. With `iters`, the
+/// body is run that many times inside a single `block_on` per iteration of the benchmark:
+///
+/// ```ignore
+/// #[smol_potat::bench(iters = 100)]
+/// async fn bench() {
+///     smol::future::yield_now().await;
+/// }
+/// ```
+///
+/// ## Warmup
+///
+/// Cold caches and lazily initialized state can make the first runs of a benchmark slower than
+/// the rest. With `warmup`, the body is run that many times before the measurement begins, each
+/// in a `block_on` of its own and with its own setup. Warmup runs are not measured:
+///
+/// ```ignore
+/// #[smol_potat::bench(warmup = 50)]
+/// async fn bench() {
+///     smol::future::yield_now().await;
+/// }
+/// ```
+///
+/// ## Threads
+///
+/// To measure how code scales under concurrent access, `threads` runs the body on the executor
+/// [`smol_potat::spawn`] spawns onto, with that many worker threads, so the tasks it spawns run
+/// in parallel. It cannot be combined with `local`:
+///
+/// ```ignore
+/// #[smol_potat::bench(threads = 4)]
+/// async fn bench() {
+///     let tasks: Vec<_> = (0..4).map(|_| smol_potat::spawn(async { work().await })).collect();
+///     for task in tasks {
+///         task.await;
+///     }
+/// }
+/// ```
+///
+/// The worker threads are started once, before the measurement, and shut down after it. Each run
+/// of the body still pays for waking the workers and for moving tasks between threads, which can
+/// outweigh a small body, so `iters` helps to measure the contention rather than the scheduling.
+///
+/// [`smol_potat::spawn`]: https://docs.rs/smol-potat/latest/smol_potat/fn.spawn.html
+///
+/// ## Setup
+///
+/// With `setup`, the expression is evaluated before each run of the body and its value is passed
+/// to the benchmark as its only parameter, which can be any pattern:
+///
+/// ```ignore
+/// #[smol_potat::bench(setup = vec![1u32; 1024])]
+/// async fn bench(data: Vec<u32>) {
+///     std::hint::black_box(data.iter().sum::<u32>());
+/// }
+/// ```
+///
+/// `test::Bencher` has no way to pause its timer, so unlike criterion's `iter_with_setup` the
+/// setup is included in the measurement, except with the `criterion` feature.
+///
+/// ## Throughput
+///
+/// With `bytes`, the throughput of the benchmark is reported as well. The value is the number of
+/// bytes processed by one run of the body:
+///
+/// ```ignore
+/// #[smol_potat::bench(bytes = 4096)]
+/// async fn bench() {
+///     std::hint::black_box(vec![0u8; 4096]);
+/// }
+/// ```
+///
+/// ## Criterion
+///
+/// The default benchmarks need nightly's `test` crate. With the `criterion` feature enabled, the
+/// benchmark is generated for [criterion] instead, which works on stable. Each benchmark becomes a
+/// function taking `&mut Criterion`, to be registered with `criterion_group!` in a bench target
+/// with `harness = false`:
+///
+/// ```ignore
+/// use criterion::{criterion_group, criterion_main};
+///
+/// #[smol_potat::bench]
+/// async fn bench() {
+///     smol::future::yield_now().await;
+/// }
+///
+/// criterion_group!(benches, bench);
+/// criterion_main!(benches);
+/// ```
+///
+/// The benchmark keeps its visibility, so benchmarks from other modules can be registered too. The
+/// setup is run outside of the measurement, and `iters` cannot be combined with `setup`.
+///
+/// [criterion]: https://docs.rs/criterion
+///
+/// ## Divan
+///
+/// The `divan` feature generates the benchmark for [divan] instead, which works on stable too.
+/// Each benchmark is registered with `#[divan::bench]`, so the bench target, with
+/// `harness = false` and `divan` as a dev-dependency, only has to call `divan::main()`:
+///
+/// ```ignore
+/// fn main() {
+///     divan::main();
+/// }
+///
+/// #[smol_potat::bench]
+/// async fn bench() {
+///     smol::future::yield_now().await;
+/// }
+/// ```
+///
+/// As with criterion, the setup is run outside of the measurement and `iters` cannot be combined
+/// with `setup`. `bytes` sets a `BytesCount` counter. If both features are enabled, `criterion`
+/// is used.
+///
+/// [divan]: https://docs.rs/divan
+#[proc_macro_attribute]
+pub fn bench(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::ItemFn);
+    let opts = syn::parse_macro_input!(attr as Opts);
+    bench::expand_bench(&opts, input)
 }
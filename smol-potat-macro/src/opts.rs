@@ -0,0 +1,1104 @@
+//! The options of the attributes and how they are parsed.
+//!
+//! Each option is kept with the span it was given at, so that errors point at it. Which options
+//! a function accepts depends on how it is run, see [`OPTIONS`].
+
+use std::num::{NonZeroU32, NonZeroU64, NonZeroUsize};
+
+use proc_macro2::Span;
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+
+#[derive(Clone)]
+pub(crate) enum Panic {
+    Abort,
+    Propagate,
+    Continue,
+}
+
+#[derive(Clone)]
+pub(crate) enum Executor {
+    Global,
+    Local,
+}
+
+#[derive(Clone)]
+pub(crate) enum Reactor {
+    Shared,
+    Dedicated,
+}
+
+#[derive(Clone)]
+pub(crate) enum Threads {
+    Num(u32),
+    Auto,
+    Env,
+    Container,
+    Expr(Box<syn::Expr>),
+    Fn(Box<syn::Expr>),
+}
+
+/// The largest literal thread count accepted, to catch typos before they spawn millions of
+/// threads. Counts computed at runtime are not checked.
+const MAX_THREADS: u32 = 4096;
+
+/// The threshold of `detect_blocking` when none is given, in milliseconds.
+pub(crate) const DETECT_BLOCKING_MS: u64 = 100;
+
+/// The window of `deadlock_detect` when none is given, in milliseconds.
+pub(crate) const DEADLOCK_DETECT_MS: u64 = 1000;
+
+/// The ways the attributes run a function, each accepting its own set of options.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Mode {
+    /// `main` on the shared executor, with worker threads.
+    Main,
+    /// `main` with `bare`.
+    Bare,
+    /// `main` with `block_on`.
+    BlockOn,
+    /// `main` with `executor = "global"`.
+    GlobalExecutor,
+    /// `main` with `local`.
+    Local,
+    /// `main` with `executor = "local"`.
+    OwnExecutor,
+    Test,
+    Bench,
+}
+
+impl Mode {
+    /// Returns whether this is one of the ways `main` runs.
+    pub(crate) fn is_main(self) -> bool {
+        !matches!(self, Mode::Test | Mode::Bench)
+    }
+
+    /// Returns what the errors about an option this mode doesn't support name.
+    fn subject(self) -> &'static str {
+        match self {
+            Mode::Main => "the main function",
+            Mode::Bare => "a bare main function",
+            Mode::BlockOn => "a custom block_on",
+            Mode::GlobalExecutor => "the global executor",
+            Mode::Local => "a local executor",
+            Mode::OwnExecutor => "executor = \"local\"",
+            Mode::Test => "tests",
+            Mode::Bench => "benchmarks",
+        }
+    }
+}
+
+/// The name of an option, the span it was given at if it was, and the modes supporting it.
+type OptionEntry = (&'static str, fn(&Opts) -> Option<Span>, &'static [Mode]);
+
+/// Every option, with the modes supporting it, in the order they are checked in.
+///
+/// A bare main has no runtime, so it only supports the options that don't need one. A custom
+/// block_on or the global executor has no worker threads to configure, and a local executor runs
+/// on the main thread alone. `expose_executor` and `join_spawned` need the shared executor, which
+/// `spawn` doesn't reach with `executor = "local"`.
+const OPTIONS: &[OptionEntry] = {
+    use Mode::*;
+    const ANY_MAIN: &[Mode] = &[Main, Bare, BlockOn, GlobalExecutor, Local, OwnExecutor];
+    const RUNTIME: &[Mode] = &[Main, BlockOn, GlobalExecutor, Local, OwnExecutor];
+    const RUNTIME_AND_TESTS: &[Mode] = &[Main, BlockOn, GlobalExecutor, Local, OwnExecutor, Test];
+    &[
+        (
+            "threads",
+            |opts| opts.threads.given(),
+            &[Main, OwnExecutor, Test, Bench],
+        ),
+        (
+            "matrix_threads",
+            |opts| opts.matrix_threads.given(),
+            &[Test],
+        ),
+        ("timeout", |opts| opts.timeout.given(), &[Test]),
+        ("retries", |opts| opts.retries.given(), RUNTIME_AND_TESTS),
+        ("backoff_ms", |opts| opts.backoff_ms.given(), RUNTIME),
+        ("iters", |opts| opts.iters.given(), &[Bench]),
+        ("warmup", |opts| opts.warmup.given(), &[Bench]),
+        ("bytes", |opts| opts.bytes.given(), &[Bench]),
+        ("local", |opts| opts.local.given(), &[Local, Test, Bench]),
+        ("catch_signals", |opts| opts.catch_signals.given(), RUNTIME),
+        ("tracing", |opts| opts.tracing.given(), RUNTIME_AND_TESTS),
+        (
+            "env_logger",
+            |opts| opts.env_logger.given(),
+            RUNTIME_AND_TESTS,
+        ),
+        ("compat", |opts| opts.compat.given(), RUNTIME_AND_TESTS),
+        ("pretty_errors", |opts| opts.pretty_errors.given(), &[Test]),
+        ("report_errors", |opts| opts.report_errors.given(), RUNTIME),
+        (
+            "expose_executor",
+            |opts| opts.expose_executor.given(),
+            &[Main],
+        ),
+        (
+            "thread_name",
+            |opts| opts.thread_name.given(),
+            &[Main, OwnExecutor, Test],
+        ),
+        (
+            "stack_size",
+            |opts| opts.stack_size.given(),
+            &[Main, OwnExecutor, Test],
+        ),
+        ("setup", |opts| opts.setup.given(), &[Test, Bench]),
+        ("teardown", |opts| opts.teardown.given(), &[Test]),
+        (
+            "reactor",
+            |opts| opts.reactor.given(),
+            &[Main, Local, OwnExecutor, Test],
+        ),
+        ("start_paused", |opts| opts.start_paused.given(), &[Test]),
+        ("ignore", |opts| opts.ignore.given(), &[Test]),
+        ("entry", |opts| opts.entry.given(), ANY_MAIN),
+        (
+            "max_concurrency",
+            |opts| opts.max_concurrency.given(),
+            &[Test],
+        ),
+        (
+            "executor",
+            |opts| opts.executor.given(),
+            &[GlobalExecutor, Local, OwnExecutor],
+        ),
+        ("isolated", |opts| opts.isolated.given(), &[Test]),
+        (
+            "shutdown_grace_ms",
+            |opts| opts.shutdown_grace_ms.given(),
+            RUNTIME,
+        ),
+        (
+            "panic",
+            |opts| opts.panic.given(),
+            &[Main, OwnExecutor, Test],
+        ),
+        (
+            "on_thread_start",
+            |opts| opts.on_thread_start.given(),
+            &[Main, OwnExecutor, Test],
+        ),
+        ("bare", |opts| opts.bare.given(), &[Bare]),
+        (
+            "pin_threads",
+            |opts| opts.pin_threads.given(),
+            &[Main, OwnExecutor, Test],
+        ),
+        ("capture_logs", |opts| opts.capture_logs.given(), &[Test]),
+        ("panic_hook", |opts| opts.panic_hook.given(), ANY_MAIN),
+        ("cases", |opts| opts.cases.given(), &[Test]),
+        ("allow_sync", |opts| opts.allow_sync.given(), ANY_MAIN),
+        ("exit_code", |opts| opts.exit_code.given(), ANY_MAIN),
+        (
+            "deadlock_detect",
+            |opts| opts.deadlock_detect.given(),
+            &[Test],
+        ),
+        ("wrap", |opts| opts.wrap.given(), ANY_MAIN),
+        ("finalizer", |opts| opts.finalizer.given(), ANY_MAIN),
+        (
+            "display_errors",
+            |opts| opts.display_errors.given(),
+            RUNTIME,
+        ),
+        ("fair", |opts| opts.fair.given(), ANY_MAIN),
+        ("cfg", |opts| opts.cfg.given(), ANY_MAIN),
+        ("task_metrics", |opts| opts.task_metrics.given(), RUNTIME),
+        ("block_on", |opts| opts.block_on.given(), &[BlockOn]),
+        (
+            "shared_executor",
+            |opts| opts.shared_executor.given(),
+            &[Test],
+        ),
+        (
+            "blocking_threads",
+            |opts| opts.blocking_threads.given(),
+            RUNTIME,
+        ),
+        ("anyhow", |opts| opts.anyhow.given(), &[Test]),
+        (
+            "detect_blocking",
+            |opts| opts.detect_blocking.given(),
+            RUNTIME,
+        ),
+        ("idle_poll_ms", |opts| opts.idle_poll_ms.given(), RUNTIME),
+        ("join_spawned", |opts| opts.join_spawned.given(), &[Main]),
+        ("seed", |opts| opts.seed.given(), &[Test]),
+        ("max_tasks", |opts| opts.max_tasks.given(), &[Test]),
+        ("diagnostics", |opts| opts.diagnostics.given(), RUNTIME),
+        (
+            "threads_config",
+            |opts| opts.threads_config.given(),
+            &[Main, OwnExecutor],
+        ),
+        ("proptest", |opts| opts.proptest.given(), &[Test]),
+    ]
+};
+
+#[derive(Clone)]
+pub(crate) struct Opts {
+    pub(crate) crate_root: syn::Path,
+    pub(crate) custom_crate_root: bool,
+    pub(crate) threads: Option<(Threads, Span)>,
+    pub(crate) matrix_threads: Option<(Vec<(u32, Span)>, Span)>,
+    pub(crate) timeout: Option<(u64, Span)>,
+    pub(crate) retries: Option<(u32, Span)>,
+    pub(crate) backoff_ms: Option<(u64, Span)>,
+    pub(crate) iters: Option<(u32, Span)>,
+    pub(crate) warmup: Option<(u32, Span)>,
+    pub(crate) bytes: Option<(u64, Span)>,
+    pub(crate) local: Option<Span>,
+    pub(crate) catch_signals: Option<Span>,
+    pub(crate) tracing: Option<Span>,
+    pub(crate) env_logger: Option<Span>,
+    pub(crate) compat: Option<Span>,
+    pub(crate) pretty_errors: Option<Span>,
+    pub(crate) report_errors: Option<Span>,
+    pub(crate) expose_executor: Option<Span>,
+    pub(crate) thread_name: Option<(String, Span)>,
+    pub(crate) stack_size: Option<(usize, Span)>,
+    pub(crate) setup: Option<(syn::Expr, Span)>,
+    pub(crate) teardown: Option<(syn::Expr, Span)>,
+    pub(crate) reactor: Option<(Reactor, Span)>,
+    pub(crate) start_paused: Option<Span>,
+    pub(crate) ignore: Option<(Option<syn::LitStr>, Span)>,
+    pub(crate) entry: Option<(bool, Span)>,
+    pub(crate) max_concurrency: Option<(usize, Span)>,
+    pub(crate) executor: Option<(Executor, Span)>,
+    pub(crate) isolated: Option<Span>,
+    pub(crate) shutdown_grace_ms: Option<(u64, Span)>,
+    pub(crate) panic: Option<(Panic, Span)>,
+    pub(crate) on_thread_start: Option<(syn::Expr, Span)>,
+    pub(crate) bare: Option<Span>,
+    pub(crate) pin_threads: Option<Span>,
+    pub(crate) capture_logs: Option<Span>,
+    pub(crate) panic_hook: Option<(syn::Expr, Span)>,
+    pub(crate) cases: Option<(Vec<syn::Expr>, Span)>,
+    pub(crate) allow_sync: Option<Span>,
+    pub(crate) exit_code: Option<Span>,
+    pub(crate) deadlock_detect: Option<(Option<u64>, Span)>,
+    pub(crate) wrap: Option<(syn::Path, Span)>,
+    pub(crate) finalizer: Option<(syn::Path, Span)>,
+    pub(crate) display_errors: Option<Span>,
+    pub(crate) fair: Option<Span>,
+    pub(crate) cfg: Option<(syn::LitStr, Span)>,
+    pub(crate) task_metrics: Option<Span>,
+    pub(crate) block_on: Option<(syn::Path, Span)>,
+    pub(crate) shared_executor: Option<Span>,
+    pub(crate) blocking_threads: Option<(usize, Span)>,
+    pub(crate) anyhow: Option<Span>,
+    pub(crate) detect_blocking: Option<(Option<u64>, Span)>,
+    pub(crate) idle_poll_ms: Option<(u64, Span)>,
+    pub(crate) join_spawned: Option<Span>,
+    pub(crate) seed: Option<(u64, Span)>,
+    pub(crate) max_tasks: Option<(usize, Span)>,
+    pub(crate) diagnostics: Option<Span>,
+    pub(crate) threads_config: Option<(syn::LitStr, Span)>,
+    pub(crate) proptest: Option<(Option<u32>, Span)>,
+}
+
+impl Opts {
+    /// Rejects the first option given that `mode` doesn't support.
+    pub(crate) fn reject_unsupported(&self, mode: Mode) -> syn::Result<()> {
+        self.reject_except(|modes| modes.contains(&mode), mode.subject())
+    }
+
+    /// Rejects the first option given whose modes aren't `supported`, naming `subject` in the
+    /// error, and points to the option's counterpart if that one is supported instead.
+    pub(crate) fn reject_except(
+        &self,
+        supported: impl Fn(&[Mode]) -> bool,
+        subject: &str,
+    ) -> syn::Result<()> {
+        let rejected = OPTIONS
+            .iter()
+            .find_map(|&(name, given, modes)| match given(self) {
+                Some(span) if !supported(modes) => Some((name, span)),
+                _ => None,
+            });
+        let (name, span) = match rejected {
+            Some(rejected) => rejected,
+            None => return Ok(()),
+        };
+
+        let counterpart = match name {
+            "report_errors" => Some("pretty_errors"),
+            "pretty_errors" | "anyhow" => Some("report_errors"),
+            _ => None,
+        };
+        let counterpart = counterpart.filter(|counterpart| {
+            OPTIONS
+                .iter()
+                .any(|&(name, _, modes)| name == *counterpart && supported(modes))
+        });
+        let message = match counterpart {
+            Some(counterpart) => format!(
+                "{} cannot have {} attribute, use {} instead",
+                subject, name, counterpart
+            ),
+            None => format!("{} cannot have {} attribute", subject, name),
+        };
+        Err(syn::Error::new(span, message))
+    }
+}
+
+/// The span of an option of `Opts`, if it was given.
+trait Given {
+    fn given(&self) -> Option<Span>;
+}
+
+impl Given for Option<Span> {
+    fn given(&self) -> Option<Span> {
+        *self
+    }
+}
+
+impl<T> Given for Option<(T, Span)> {
+    fn given(&self) -> Option<Span> {
+        self.as_ref().map(|(_, span)| *span)
+    }
+}
+
+impl Parse for Opts {
+    fn parse(input: ParseStream<'_>) -> syn::Result<Self> {
+        let mut crate_root = None;
+        let mut threads = None;
+        let mut threads_key = None;
+        let mut threads_fn = None;
+        let mut matrix_threads = None;
+        let mut timeout = None;
+        let mut retries = None;
+        let mut backoff_ms = None;
+        let mut iters = None;
+        let mut warmup = None;
+        let mut bytes = None;
+        let mut local = None;
+        let mut catch_signals = None;
+        let mut tracing = None;
+        let mut env_logger = None;
+        let mut compat = None;
+        let mut pretty_errors = None;
+        let mut report_errors = None;
+        let mut expose_executor = None;
+        let mut thread_name = None;
+        let mut stack_size = None;
+        let mut setup = None;
+        let mut teardown = None;
+        let mut reactor = None;
+        let mut start_paused = None;
+        let mut ignore = None;
+        let mut entry = None;
+        let mut max_concurrency = None;
+        let mut executor = None;
+        let mut isolated = None;
+        let mut shutdown_grace_ms = None;
+        let mut panic = None;
+        let mut on_thread_start = None;
+        let mut bare = None;
+        let mut pin_threads = None;
+        let mut capture_logs = None;
+        let mut panic_hook = None;
+        let mut cases = None;
+        let mut allow_sync = None;
+        let mut exit_code = None;
+        let mut deadlock_detect = None;
+        let mut wrap = None;
+        let mut finalizer = None;
+        let mut display_errors = None;
+        let mut fair = None;
+        let mut cfg = None;
+        let mut task_metrics = None;
+        let mut block_on = None;
+        let mut shared_executor = None;
+        let mut blocking_threads = None;
+        let mut anyhow = None;
+        let mut detect_blocking = None;
+        let mut idle_poll_ms = None;
+        let mut join_spawned = None;
+        let mut seed = None;
+        let mut max_tasks = None;
+        let mut diagnostics = None;
+        let mut threads_config = None;
+        let mut proptest = None;
+
+        loop {
+            if input.is_empty() {
+                break;
+            }
+
+            let path = input.call(syn::Path::parse_mod_style)?;
+            let ident = match path.get_ident() {
+                Some(ident) => ident,
+                None => return Err(syn::Error::new_spanned(path, "Must be a single ident")),
+            };
+            let value = if input.peek(syn::Token![=]) {
+                input.parse::<syn::Token![=]>()?;
+                Some(input.parse::<syn::Expr>()?)
+            } else if ident == "proptest" && input.peek(syn::token::Paren) {
+                // `proptest(cases = 50)` nests its own options.
+                Some(input.parse::<syn::Expr>()?)
+            } else {
+                None
+            };
+
+            let name = ident.to_string().to_lowercase();
+            match &*name {
+                // `worker_threads` is accepted for people coming from `#[tokio::main]`.
+                "threads" | "worker_threads" => {
+                    let value = expect_value(ident, value)?;
+                    match threads_key {
+                        Some(previous) if previous == name => {
+                            return Err(syn::Error::new_spanned(
+                                value,
+                                format!("multiple {} arguments", name),
+                            ));
+                        }
+                        Some(_) => {
+                            return Err(syn::Error::new_spanned(
+                                ident,
+                                "`threads` and `worker_threads` are aliases, only one can be used",
+                            ));
+                        }
+                        None => threads_key = Some(name.clone()),
+                    }
+
+                    threads = Some(match value {
+                        syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Int(lit),
+                            ..
+                        }) => {
+                            let num = lit.base10_parse::<u32>()?;
+                            if num > MAX_THREADS {
+                                return Err(syn::Error::new_spanned(
+                                    lit,
+                                    format!("threads argument must be at most {}", MAX_THREADS),
+                                ));
+                            }
+                            // As in other runtimes, no number means one thread per CPU.
+                            match num {
+                                0 => (Threads::Auto, lit.span()),
+                                num => (Threads::Num(num), lit.span()),
+                            }
+                        }
+                        syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(lit),
+                            ..
+                        }) => {
+                            let threads = match &*lit.value() {
+                                "auto" => Threads::Auto,
+                                "respect_env" => Threads::Env,
+                                "container" => Threads::Container,
+                                _ => {
+                                    return Err(syn::Error::new_spanned(
+                                        lit,
+                                        "threads argument must be an integer, \"auto\", \"respect_env\", \"container\" or an expression",
+                                    ));
+                                }
+                            };
+
+                            (threads, lit.span())
+                        }
+                        expr => {
+                            let span = expr.span();
+                            (Threads::Expr(Box::new(expr)), span)
+                        }
+                    });
+                }
+                "matrix_threads" => {
+                    let value = value_once(&matrix_threads, &name, ident, value)?;
+                    let array = match value {
+                        syn::Expr::Array(array) if !array.elems.is_empty() => array,
+                        value => {
+                            return Err(syn::Error::new_spanned(
+                                value,
+                                "matrix_threads argument must be a non-empty array of integers",
+                            ));
+                        }
+                    };
+                    let mut counts: Vec<(u32, Span)> = Vec::new();
+                    for elem in &array.elems {
+                        let lit = match elem {
+                            syn::Expr::Lit(syn::ExprLit {
+                                lit: syn::Lit::Int(lit),
+                                ..
+                            }) => lit,
+                            elem => {
+                                return Err(syn::Error::new_spanned(
+                                    elem,
+                                    "matrix_threads argument must be a non-empty array of integers",
+                                ));
+                            }
+                        };
+                        let num = lit.base10_parse::<NonZeroU32>()?.get();
+                        if num > MAX_THREADS {
+                            return Err(syn::Error::new_spanned(
+                                lit,
+                                format!("matrix_threads counts must be at most {}", MAX_THREADS),
+                            ));
+                        }
+                        if counts.iter().any(|(count, _)| *count == num) {
+                            return Err(syn::Error::new_spanned(
+                                lit,
+                                format!("duplicate thread count {} in matrix_threads", num),
+                            ));
+                        }
+                        counts.push((num, lit.span()));
+                    }
+
+                    matrix_threads = Some((counts, ident.span()));
+                }
+                "cases" => match value_once(&cases, &name, ident, value)? {
+                    syn::Expr::Array(array) if !array.elems.is_empty() => {
+                        cases = Some((array.elems.into_iter().collect(), ident.span()));
+                    }
+                    value => {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "cases argument must be a non-empty array",
+                        ));
+                    }
+                },
+                "timeout" => {
+                    let value = value_once(&timeout, &name, ident, value)?;
+                    let (ms, span) = int_value::<NonZeroU64>(value, &name)?;
+                    timeout = Some((ms.get(), span));
+                }
+                "retries" => {
+                    let value = value_once(&retries, &name, ident, value)?;
+                    retries = Some(int_value(value, &name)?);
+                }
+                "backoff_ms" => {
+                    let value = value_once(&backoff_ms, &name, ident, value)?;
+                    backoff_ms = Some(int_value(value, &name)?);
+                }
+                "iters" => {
+                    let value = value_once(&iters, &name, ident, value)?;
+                    let (num, span) = int_value::<NonZeroU32>(value, &name)?;
+                    iters = Some((num.get(), span));
+                }
+                "warmup" => {
+                    let value = value_once(&warmup, &name, ident, value)?;
+                    warmup = Some(int_value(value, &name)?);
+                }
+                "bytes" => {
+                    let value = value_once(&bytes, &name, ident, value)?;
+                    bytes = Some(int_value(value, &name)?);
+                }
+                "crate" => match value_once(&crate_root, &name, ident, value)? {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(lit),
+                        ..
+                    }) => {
+                        crate_root = Some(lit.parse()?);
+                    }
+                    syn::Expr::Path(syn::ExprPath {
+                        qself: None, path, ..
+                    }) => {
+                        crate_root = Some(path);
+                    }
+                    value => {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "crate argument must be a path or a string",
+                        ))
+                    }
+                },
+                "thread_name" => {
+                    let value = value_once(&thread_name, &name, ident, value)?;
+                    let lit = str_value(value, "thread_name argument must be a string")?;
+                    thread_name = Some((lit.value(), lit.span()));
+                }
+                "stack_size" => {
+                    let value = value_once(&stack_size, &name, ident, value)?;
+                    let (bytes, span) = int_value::<NonZeroUsize>(value, &name)?;
+                    stack_size = Some((bytes.get(), span));
+                }
+                "blocking_threads" => {
+                    let value = value_once(&blocking_threads, &name, ident, value)?;
+                    let (threads, span) = int_value::<NonZeroUsize>(value, &name)?;
+                    blocking_threads = Some((threads.get(), span));
+                }
+                "proptest" => {
+                    require_feature(cfg!(feature = "proptest"), &name, ident, "proptest")?;
+                    reject_repeat(&proptest, &name, ident)?;
+
+                    let options = match value {
+                        None => Vec::new(),
+                        Some(syn::Expr::Paren(paren)) => vec![*paren.expr],
+                        Some(syn::Expr::Tuple(tuple)) => tuple.elems.into_iter().collect(),
+                        Some(value) => {
+                            return Err(syn::Error::new_spanned(
+                                value,
+                                "proptest arguments must be written as `proptest(cases = N)`",
+                            ))
+                        }
+                    };
+                    let mut cases = None;
+                    for option in options {
+                        let (key, value) =
+                            match option {
+                                syn::Expr::Assign(assign) => (assign.left, assign.right),
+                                option => return Err(syn::Error::new_spanned(
+                                    option,
+                                    "proptest arguments must be written as `proptest(cases = N)`",
+                                )),
+                            };
+                        match &*key {
+                            syn::Expr::Path(path) if path.path.is_ident("cases") => {}
+                            key => {
+                                return Err(syn::Error::new_spanned(
+                                    key,
+                                    "unknown proptest argument, expected `cases`",
+                                ))
+                            }
+                        }
+                        reject_repeat(&cases, "proptest cases", key)?;
+                        let (num, _) = int_value::<NonZeroU32>(*value, "proptest cases")?;
+                        cases = Some(num.get());
+                    }
+
+                    proptest = Some((cases, ident.span()));
+                }
+                "threads_config" => {
+                    let value = expect_value(ident, value)?;
+                    require_feature(cfg!(feature = "config"), &name, ident, "config")?;
+                    reject_repeat(&threads_config, &name, &value)?;
+                    let lit = str_value(value, "threads_config argument must be a string")?;
+                    threads_config = Some((lit, ident.span()));
+                }
+                "threads_fn" => {
+                    threads_fn = Some(value_once(&threads_fn, &name, ident, value)?);
+                }
+                "setup" => {
+                    setup = Some((value_once(&setup, &name, ident, value)?, ident.span()));
+                }
+                "teardown" => {
+                    teardown = Some((value_once(&teardown, &name, ident, value)?, ident.span()));
+                }
+                "panic_hook" => {
+                    let value = value_once(&panic_hook, &name, ident, value)?;
+                    panic_hook = Some((value, ident.span()));
+                }
+                "on_thread_start" => {
+                    let value = value_once(&on_thread_start, &name, ident, value)?;
+                    let span = value.span();
+                    on_thread_start = Some((value, span));
+                }
+                "wrap" => {
+                    let value = value_once(&wrap, &name, ident, value)?;
+                    wrap = Some((path_value(value, &name)?, ident.span()));
+                }
+                "finalizer" => {
+                    let value = value_once(&finalizer, &name, ident, value)?;
+                    finalizer = Some((path_value(value, &name)?, ident.span()));
+                }
+                "block_on" => {
+                    let value = value_once(&block_on, &name, ident, value)?;
+                    block_on = Some((path_value(value, &name)?, ident.span()));
+                }
+                "cfg" => {
+                    let value = value_once(&cfg, &name, ident, value)?;
+                    let lit = str_value(value, "cfg argument must be the name of a feature")?;
+                    cfg = Some((lit, ident.span()));
+                }
+                "reactor" => {
+                    let value = value_once(&reactor, &name, ident, value)?;
+                    let message = "reactor argument must be \"shared\" or \"dedicated\"";
+                    let lit = str_value(value, message)?;
+                    let kind = match &*lit.value() {
+                        "shared" => Reactor::Shared,
+                        "dedicated" => Reactor::Dedicated,
+                        _ => return Err(syn::Error::new_spanned(lit, message)),
+                    };
+
+                    reactor = Some((kind, lit.span()));
+                }
+                "panic" => {
+                    let value = value_once(&panic, &name, ident, value)?;
+                    let message = "panic argument must be \"abort\", \"propagate\" or \"continue\"";
+                    let lit = str_value(value, message)?;
+                    let policy = match &*lit.value() {
+                        "abort" => Panic::Abort,
+                        "propagate" => Panic::Propagate,
+                        "continue" => Panic::Continue,
+                        _ => return Err(syn::Error::new_spanned(lit, message)),
+                    };
+
+                    panic = Some((policy, lit.span()));
+                }
+                "executor" => {
+                    let value = value_once(&executor, &name, ident, value)?;
+                    let message = "executor argument must be \"global\" or \"local\"";
+                    let lit = str_value(value, message)?;
+                    let kind = match &*lit.value() {
+                        "global" if !cfg!(feature = "global-executor") => {
+                            return Err(syn::Error::new_spanned(
+                                lit,
+                                "executor = \"global\" requires the `global-executor` feature of smol-potat",
+                            ))
+                        }
+                        "global" => Executor::Global,
+                        "local" => Executor::Local,
+                        _ => return Err(syn::Error::new_spanned(lit, message)),
+                    };
+
+                    executor = Some((kind, lit.span()));
+                }
+                "entry" => match value_once(&entry, &name, ident, value)? {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Bool(lit),
+                        ..
+                    }) => entry = Some((lit.value, lit.span)),
+                    value => {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "entry argument must be a boolean",
+                        ))
+                    }
+                },
+                "ignore" => {
+                    reject_repeat(&ignore, &name, ident)?;
+                    let reason = value
+                        .map(|value| str_value(value, "ignore argument must be a string"))
+                        .transpose()?;
+                    ignore = Some((reason, ident.span()));
+                }
+                "detect_blocking" => {
+                    reject_repeat(&detect_blocking, &name, ident)?;
+                    let threshold = value
+                        .map(|value| int_value::<NonZeroU64>(value, &name))
+                        .transpose()?;
+                    detect_blocking = Some((threshold.map(|(ms, _)| ms.get()), ident.span()));
+                }
+                "deadlock_detect" => {
+                    reject_repeat(&deadlock_detect, &name, ident)?;
+                    let window = value
+                        .map(|value| int_value::<NonZeroU64>(value, &name))
+                        .transpose()?;
+                    deadlock_detect = Some((window.map(|(ms, _)| ms.get()), ident.span()));
+                }
+                "idle_poll_ms" => {
+                    let value = value_once(&idle_poll_ms, &name, ident, value)?;
+                    let (ms, span) = int_value::<NonZeroU64>(value, &name)?;
+                    idle_poll_ms = Some((ms.get(), span));
+                }
+                "shutdown_grace_ms" => {
+                    let value = value_once(&shutdown_grace_ms, &name, ident, value)?;
+                    shutdown_grace_ms = Some(int_value(value, &name)?);
+                }
+                "seed" => {
+                    let value = value_once(&seed, &name, ident, value)?;
+                    seed = Some(int_value(value, &name)?);
+                }
+                "max_tasks" => {
+                    let value = value_once(&max_tasks, &name, ident, value)?;
+                    max_tasks = Some(int_value(value, &name)?);
+                }
+                "max_concurrency" => {
+                    let value = value_once(&max_concurrency, &name, ident, value)?;
+                    let (num, span) = int_value::<NonZeroUsize>(value, &name)?;
+                    max_concurrency = Some((num.get(), span));
+                }
+                "catch_signals" => {
+                    if !cfg!(any(feature = "async-signal", feature = "ctrlc-integration")) {
+                        return Err(syn::Error::new_spanned(
+                            ident,
+                            "the catch_signals option requires the `async-signal` or `ctrlc-integration` feature of smol-potat",
+                        ));
+                    }
+                    flag(&mut catch_signals, &name, ident, value)?;
+                }
+                "tracing" => {
+                    require_feature(cfg!(feature = "tracing"), &name, ident, "tracing")?;
+                    flag(&mut tracing, &name, ident, value)?;
+                }
+                "env_logger" => {
+                    require_feature(cfg!(feature = "env_logger"), &name, ident, "env_logger")?;
+                    flag(&mut env_logger, &name, ident, value)?;
+                }
+                "compat" => {
+                    require_feature(cfg!(feature = "compat"), &name, ident, "compat")?;
+                    flag(&mut compat, &name, ident, value)?;
+                }
+                "pin_threads" => {
+                    require_feature(
+                        cfg!(feature = "core_affinity"),
+                        &name,
+                        ident,
+                        "core_affinity",
+                    )?;
+                    flag(&mut pin_threads, &name, ident, value)?;
+                }
+                "capture_logs" => {
+                    require_feature(cfg!(feature = "log"), &name, ident, "log")?;
+                    flag(&mut capture_logs, &name, ident, value)?;
+                }
+                "anyhow" => {
+                    require_feature(cfg!(feature = "anyhow"), &name, ident, "anyhow")?;
+                    flag(&mut anyhow, &name, ident, value)?;
+                }
+                "diagnostics" => {
+                    require_feature(cfg!(feature = "diagnostics"), &name, ident, "diagnostics")?;
+                    flag(&mut diagnostics, &name, ident, value)?;
+                }
+                "local" => flag(&mut local, &name, ident, value)?,
+                "start_paused" => flag(&mut start_paused, &name, ident, value)?,
+                "bare" => flag(&mut bare, &name, ident, value)?,
+                "allow_sync" => flag(&mut allow_sync, &name, ident, value)?,
+                "exit_code" => flag(&mut exit_code, &name, ident, value)?,
+                "display_errors" => flag(&mut display_errors, &name, ident, value)?,
+                "fair" => flag(&mut fair, &name, ident, value)?,
+                "task_metrics" => flag(&mut task_metrics, &name, ident, value)?,
+                "shared_executor" => flag(&mut shared_executor, &name, ident, value)?,
+                "join_spawned" => flag(&mut join_spawned, &name, ident, value)?,
+                "isolated" => flag(&mut isolated, &name, ident, value)?,
+                "pretty_errors" => flag(&mut pretty_errors, &name, ident, value)?,
+                "report_errors" => flag(&mut report_errors, &name, ident, value)?,
+                "expose_executor" => flag(&mut expose_executor, &name, ident, value)?,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        ident,
+                        format!("unknown attribute `{}`", name),
+                    ));
+                }
+            }
+
+            // Options are separated by commas, and a trailing comma is allowed.
+            if !input.is_empty() {
+                input.parse::<syn::Token![,]>()?;
+            }
+        }
+
+        if let Some(threads_fn) = threads_fn {
+            if threads.is_some() {
+                return Err(syn::Error::new_spanned(
+                    threads_fn,
+                    "the threads and threads_fn options cannot be combined",
+                ));
+            }
+            let span = threads_fn.span();
+            threads = Some((Threads::Fn(Box::new(threads_fn)), span));
+        }
+
+        if let (Some(_), Some(span)) = (report_errors, display_errors) {
+            return Err(syn::Error::new(
+                span,
+                "the report_errors and display_errors options cannot be combined",
+            ));
+        }
+        // A cancelled main returns normally, which an exit code can't express.
+        if let (Some(_), Some(span)) = (catch_signals, exit_code) {
+            return Err(syn::Error::new(
+                span,
+                "the catch_signals and exit_code options cannot be combined",
+            ));
+        }
+        if let (Some(_), Some(span)) = (pretty_errors, anyhow) {
+            return Err(syn::Error::new(
+                span,
+                "the pretty_errors and anyhow options cannot be combined",
+            ));
+        }
+        // Both would install themselves as the one global logger.
+        if let (Some(_), Some(span)) = (tracing, env_logger) {
+            return Err(syn::Error::new(
+                span,
+                "the tracing and env_logger options cannot be combined",
+            ));
+        }
+        if let (Some(_), Some(span)) = (tracing.or(env_logger), capture_logs) {
+            return Err(syn::Error::new(
+                span,
+                "capture_logs cannot be combined with the tracing or env_logger options",
+            ));
+        }
+        // The inputs of a proptest come from its strategies, and a failing case is shrunk instead
+        // of retried.
+        if let Some((_, span)) = proptest {
+            if cases.is_some() || retries.is_some() || setup.is_some() {
+                return Err(syn::Error::new(
+                    span,
+                    "proptest cannot be combined with the cases, retries or setup options",
+                ));
+            }
+        }
+
+        Ok(Self {
+            custom_crate_root: crate_root.is_some(),
+            crate_root: crate_root.unwrap_or_else(|| syn::parse2(quote!(::smol_potat)).unwrap()),
+            threads,
+            matrix_threads,
+            timeout,
+            retries,
+            backoff_ms,
+            iters,
+            warmup,
+            bytes,
+            local,
+            catch_signals,
+            tracing,
+            env_logger,
+            compat,
+            pretty_errors,
+            report_errors,
+            expose_executor,
+            thread_name,
+            stack_size,
+            setup,
+            teardown,
+            reactor,
+            start_paused,
+            ignore,
+            entry,
+            max_concurrency,
+            executor,
+            isolated,
+            shutdown_grace_ms,
+            panic,
+            on_thread_start,
+            bare,
+            pin_threads,
+            capture_logs,
+            panic_hook,
+            cases,
+            allow_sync,
+            exit_code,
+            deadlock_detect,
+            wrap,
+            finalizer,
+            display_errors,
+            fair,
+            cfg,
+            task_metrics,
+            block_on,
+            shared_executor,
+            blocking_threads,
+            anyhow,
+            detect_blocking,
+            idle_poll_ms,
+            join_spawned,
+            seed,
+            max_tasks,
+            diagnostics,
+            threads_config,
+            proptest,
+        })
+    }
+}
+
+fn expect_value(ident: &syn::Ident, value: Option<syn::Expr>) -> syn::Result<syn::Expr> {
+    value.ok_or_else(|| {
+        syn::Error::new_spanned(ident, format!("{} argument must have a value", ident))
+    })
+}
+
+fn expect_flag(ident: &syn::Ident, value: Option<syn::Expr>) -> syn::Result<()> {
+    match value {
+        Some(value) => Err(syn::Error::new_spanned(
+            value,
+            format!("{} argument does not take a value", ident),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Returns the value of the option `name`, which must have one and can only be given once.
+fn value_once<T>(
+    slot: &Option<T>,
+    name: &str,
+    ident: &syn::Ident,
+    value: Option<syn::Expr>,
+) -> syn::Result<syn::Expr> {
+    let value = expect_value(ident, value)?;
+    reject_repeat(slot, name, &value)?;
+    Ok(value)
+}
+
+/// Parses the flag `name`, which takes no value and can only be given once.
+fn flag(
+    slot: &mut Option<Span>,
+    name: &str,
+    ident: &syn::Ident,
+    value: Option<syn::Expr>,
+) -> syn::Result<()> {
+    expect_flag(ident, value)?;
+    reject_repeat(slot, name, ident)?;
+    *slot = Some(ident.span());
+    Ok(())
+}
+
+/// Rejects the option `name` if it was already given, pointing at `tokens`.
+fn reject_repeat<T>(slot: &Option<T>, name: &str, tokens: impl ToTokens) -> syn::Result<()> {
+    match slot {
+        Some(_) => Err(syn::Error::new_spanned(
+            tokens,
+            format!("multiple {} arguments", name),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Rejects the option `name` unless the `feature` of smol-potat it needs is `enabled`.
+fn require_feature(
+    enabled: bool,
+    name: &str,
+    ident: &syn::Ident,
+    feature: &str,
+) -> syn::Result<()> {
+    if enabled {
+        return Ok(());
+    }
+    Err(syn::Error::new_spanned(
+        ident,
+        format!(
+            "the {} option requires the `{}` feature of smol-potat",
+            name, feature
+        ),
+    ))
+}
+
+/// Parses the integer literal given to the option `name`, returning it with its span.
+fn int_value<N>(value: syn::Expr, name: &str) -> syn::Result<(N, Span)>
+where
+    N: std::str::FromStr,
+    N::Err: std::fmt::Display,
+{
+    match value {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Int(lit),
+            ..
+        }) => Ok((lit.base10_parse()?, lit.span())),
+        value => Err(syn::Error::new_spanned(
+            value,
+            format!("{} argument must be an integer", name),
+        )),
+    }
+}
+
+/// Returns the string literal given to an option, or an error with `message` for anything else.
+fn str_value(value: syn::Expr, message: &str) -> syn::Result<syn::LitStr> {
+    match value {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(lit),
+            ..
+        }) => Ok(lit),
+        value => Err(syn::Error::new_spanned(value, message)),
+    }
+}
+
+/// Returns the path given to the option `name`.
+fn path_value(value: syn::Expr, name: &str) -> syn::Result<syn::Path> {
+    match value {
+        syn::Expr::Path(syn::ExprPath {
+            qself: None, path, ..
+        }) => Ok(path),
+        value => Err(syn::Error::new_spanned(
+            value,
+            format!("{} argument must be a path", name),
+        )),
+    }
+}
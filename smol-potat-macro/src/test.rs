@@ -0,0 +1,640 @@
+//! The expansion of `#[smol_potat::test]`.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+
+use crate::opts::{Mode, Opts, Threads, DEADLOCK_DETECT_MS};
+use crate::util::{
+    internal_ident, monomorphize, normalize_unit, reactor_tokens, result_ok_type, threads_tokens,
+    with_crate_root, workers_tokens, wrapper_generics,
+};
+
+/// Generates the tests for a single async test function.
+pub(crate) fn expand_test(opts: &Opts, mut input: syn::ItemFn) -> TokenStream {
+    let crate_root = &opts.crate_root;
+
+    if input.sig.asyncness.is_none() {
+        // A function returning `impl Future<Output = T>` is treated as an async function
+        // returning `T` that awaits the future.
+        let output = match future_output(&input.sig.output) {
+            Some(output) => output.clone(),
+            None => {
+                return TokenStream::from(quote_spanned! { input.span() =>
+                    compile_error!("the async keyword is missing from the function declaration");
+                })
+            }
+        };
+        let body = &input.block;
+        input.block = syn::parse_quote!({ (#body).await });
+        input.sig.output = syn::parse_quote!(-> #output);
+        input.sig.asyncness = Some(Default::default());
+    }
+    normalize_unit(&mut input.sig.output);
+
+    // The fixture is set up at the start of the body, bound to the test's parameter, and the
+    // teardown guard is created right after it.
+    if let Some((teardown, _)) = &opts.teardown {
+        let guard = internal_ident("teardown");
+        input.block.stmts.insert(
+            0,
+            syn::parse_quote! {
+                let #guard = #crate_root::__private::teardown(#teardown);
+            },
+        );
+    }
+    if let Some((setup, _)) = &opts.setup {
+        let param = match input.sig.inputs.first() {
+            Some(syn::FnArg::Typed(param)) if input.sig.inputs.len() == 1 => param.clone(),
+            _ => {
+                return TokenStream::from(quote_spanned! { input.sig.paren_token.span =>
+                    compile_error!("the test must take the value returned by setup as its parameter");
+                })
+            }
+        };
+        let (pat, ty) = (&param.pat, &param.ty);
+        input.block.stmts.insert(
+            0,
+            syn::parse_quote! {
+                let #pat: #ty = (#setup)().await;
+            },
+        );
+        input.sig.inputs.clear();
+    }
+
+    // The inputs of a proptest are generated together as a tuple, which is destructured at the
+    // start of the body.
+    let proptest_values = internal_ident("values");
+    let proptest = match opts.proptest {
+        Some((cases, _)) => match proptest_strategies(&mut input, crate_root, &proptest_values) {
+            Ok(strategies) => Some((strategies, cases)),
+            Err(err) => return err.to_compile_error().into(),
+        },
+        None => None,
+    };
+
+    // Seeded at the start of the body, on the thread polling it, so every retry starts over from
+    // the same seed.
+    if let Some((seed, span)) = opts.seed {
+        let guard = internal_ident("seed_guard");
+        input.block.stmts.splice(
+            0..0,
+            [
+                syn::parse_quote_spanned! { span=>
+                    #crate_root::fastrand::seed(#seed);
+                },
+                syn::parse_quote_spanned! { span=>
+                    let #guard = #crate_root::__private::SeedGuard(#seed);
+                },
+            ],
+        );
+    }
+
+    // Every phase of the test blocks on the same runtime, so the tasks spawned in one phase keep
+    // running in the next.
+    if opts.shared_executor.is_some() {
+        input.block.stmts.splice(
+            0..0,
+            [
+                syn::parse_quote! {
+                    let runtime = #crate_root::Runtime::new();
+                },
+                syn::parse_quote! {
+                    let executor = #crate_root::std::sync::Arc::clone(runtime.executor());
+                },
+            ],
+        );
+    }
+
+    if opts.capture_logs.is_some() {
+        input.block.stmts.insert(
+            0,
+            syn::parse_quote! {
+                let logs = #crate_root::__private::captured_logs();
+            },
+        );
+    }
+
+    // The semaphore is bound at the start of the body itself, so it is in scope even if the body
+    // ends up in an inner function.
+    if let Some((permits, _)) = opts.max_concurrency {
+        input.block.stmts.insert(
+            0,
+            syn::parse_quote! {
+                let semaphore = #crate_root::std::sync::Arc::new(
+                    #crate_root::async_lock::Semaphore::new(#permits),
+                );
+            },
+        );
+    }
+
+    let ret = &input.sig.output;
+    let name = &input.sig.ident;
+    // The macro adds `#[test]` itself, so one written by hand would make it a duplicate.
+    let attrs: Vec<_> = input
+        .attrs
+        .iter()
+        .filter(|attr| !attr.path.is_ident("test"))
+        .collect();
+
+    if let Err(err) = opts.reject_unsupported(Mode::Test) {
+        return err.to_compile_error().into();
+    }
+
+    if !input.sig.inputs.is_empty() {
+        return TokenStream::from(quote_spanned! { input.span() =>
+            compile_error!("tests cannot take parameters, except for the value returned by setup");
+        });
+    }
+    let body = match monomorphize(&input) {
+        Ok(body) => body,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let (lifetimes, where_clause) = wrapper_generics(&input.sig.generics);
+
+    let errors = match (opts.pretty_errors, opts.anyhow) {
+        (Some(span), _) => Some((span, "pretty_errors")),
+        (None, Some(span)) => Some((span, "anyhow")),
+        (None, None) => None,
+    };
+    let (body, ret) = match errors {
+        Some((span, option)) => {
+            let (result, ok) = match result_ok_type(&input.sig.output) {
+                Some(types) => types,
+                None => {
+                    let message = format!("{} requires the test to return a `Result`", option);
+                    return TokenStream::from(quote_spanned! { span=>
+                        compile_error!(#message);
+                    });
+                }
+            };
+            let fail = match opts.anyhow {
+                Some(_) => quote!(#crate_root::__private::fail_anyhow(err)),
+                None => quote! {
+                    #crate_root::std::panic!("{}", #crate_root::__private::format_error(err))
+                },
+            };
+            let body = quote! {
+                {
+                    let result: #result = async #body.await;
+                    match result {
+                        #crate_root::std::result::Result::Ok(output) => output,
+                        #crate_root::std::result::Result::Err(err) => #fail,
+                    }
+                }
+            };
+            (body, quote!(-> #ok))
+        }
+        None => (body, quote!(#ret)),
+    };
+
+    let timeout = match opts.timeout {
+        Some((ms, _)) => quote!(#crate_root::std::option::Option::Some(#ms)),
+        None => quote!(#crate_root::__private::env_test_timeout()),
+    };
+    let body = {
+        let future = internal_ident("future");
+        let output = internal_ident("output");
+        let ms = internal_ident("ms");
+        let message = format!("test '{}' timed out after {{}}ms", name);
+        // The race is over before the panic, so the test's future has already been dropped and
+        // its destructors have run instead of being leaked or run during unwinding.
+        quote! {
+            {
+                let #future = async #body;
+                match #timeout {
+                    #crate_root::std::option::Option::Some(#ms) => {
+                        let #output = #crate_root::futures_lite::future::or(
+                            async { #crate_root::std::option::Option::Some(#future.await) },
+                            async {
+                                #crate_root::async_io::Timer::after(
+                                    #crate_root::std::time::Duration::from_millis(#ms),
+                                )
+                                .await;
+                                #crate_root::std::option::Option::None
+                            },
+                        )
+                        .await;
+                        match #output {
+                            #crate_root::std::option::Option::Some(#output) => #output,
+                            #crate_root::std::option::Option::None => {
+                                #crate_root::std::panic!(#message, #ms)
+                            }
+                        }
+                    }
+                    #crate_root::std::option::Option::None => #future.await,
+                }
+            }
+        }
+    };
+
+    let body = match opts.deadlock_detect {
+        Some((window, _)) => {
+            let name = name.to_string();
+            let ms = window.unwrap_or(DEADLOCK_DETECT_MS);
+            quote! {
+                {
+                    #crate_root::__private::detect_deadlock(
+                        #name,
+                        #crate_root::std::time::Duration::from_millis(#ms),
+                        async #body,
+                    )
+                    .await
+                }
+            }
+        }
+        None => body,
+    };
+
+    let body = match opts.max_tasks {
+        Some((max, _)) => quote! {
+            {
+                #crate_root::__private::max_tasks(#max, async #body).await
+            }
+        },
+        None => body,
+    };
+
+    // A panic in a detached task would otherwise only be reported, so the test couldn't observe
+    // it. Only the tasks spawned by this test count, as the workers are shared with other tests.
+    let threaded = opts.threads.is_some() || opts.matrix_threads.is_some();
+    let should_panic = attrs.iter().any(|attr| attr.path.is_ident("should_panic"));
+    let body = match opts.panic {
+        None if threaded && should_panic => quote! {
+            {
+                #crate_root::__private::propagate_task_panics(async #body).await
+            }
+        },
+        _ => body,
+    };
+
+    let body = match opts.compat {
+        Some(_) => quote! {
+            {
+                #crate_root::async_compat::Compat::new(async #body).await
+            }
+        },
+        None => body,
+    };
+
+    let body = match opts.start_paused {
+        Some(span) => {
+            // The clock is advanced when the test is idle, which it can't tell while other
+            // threads or spawned tasks may still be working.
+            if opts.local.is_some() || opts.threads.is_some() || opts.matrix_threads.is_some() {
+                return TokenStream::from(quote_spanned! { span=>
+                    compile_error!("start_paused cannot be combined with threads or local");
+                });
+            }
+
+            quote! {
+                {
+                    #crate_root::__private::start_paused(async #body).await
+                }
+            }
+        }
+        None => body,
+    };
+
+    let body = match opts.capture_logs {
+        Some(_) => quote! {
+            {
+                #crate_root::__private::capture_logs(async #body).await
+            }
+        },
+        None => body,
+    };
+
+    if opts.local.is_some() {
+        if let Some((_, span)) = opts.threads {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("a local executor cannot have threads attribute");
+            });
+        }
+    }
+    if let Some((_, span)) = opts.thread_name {
+        if opts.threads.is_none() && opts.matrix_threads.is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("thread_name requires the threads or matrix_threads attribute");
+            });
+        }
+    }
+    if let Some((_, span)) = opts.stack_size {
+        if opts.threads.is_none() && opts.matrix_threads.is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("stack_size requires the threads or matrix_threads attribute");
+            });
+        }
+    }
+    if let Some((_, span)) = opts.panic {
+        if opts.threads.is_none() && opts.matrix_threads.is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("panic requires the threads or matrix_threads attribute");
+            });
+        }
+    }
+    if let Some((_, span)) = opts.on_thread_start {
+        if opts.threads.is_none() && opts.matrix_threads.is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("on_thread_start requires the threads or matrix_threads attribute");
+            });
+        }
+    }
+    if let Some(span) = opts.pin_threads {
+        if opts.threads.is_none() && opts.matrix_threads.is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("pin_threads requires the threads or matrix_threads attribute");
+            });
+        }
+    }
+    if let Some((_, span)) = opts.deadlock_detect {
+        if opts.threads.is_none() && opts.matrix_threads.is_none() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("deadlock_detect requires the threads or matrix_threads attribute");
+            });
+        }
+    }
+    if let Some((_, span)) = opts.matrix_threads {
+        if opts.local.is_some() || opts.threads.is_some() {
+            return TokenStream::from(quote_spanned! { span=>
+                compile_error!("matrix_threads cannot be combined with threads or local");
+            });
+        }
+    }
+
+    // Each entry of `matrix_threads` becomes its own test, named after its thread count.
+    let variants: Vec<(syn::Ident, Option<(Threads, Span)>)> = match &opts.matrix_threads {
+        Some((counts, _)) => counts
+            .iter()
+            .map(|&(count, span)| {
+                let name = quote::format_ident!("{}_threads_{}", name, count);
+                (name, Some((Threads::Num(count), span)))
+            })
+            .collect(),
+        None => vec![(name.clone(), opts.threads.clone())],
+    };
+
+    let init_tracing = opts.tracing.map(|_| {
+        quote! {
+            let _ = #crate_root::tracing_subscriber::fmt()
+                .with_test_writer()
+                .try_init();
+        }
+    });
+    let init_env_logger = opts.env_logger.map(|_| {
+        quote! {
+            let _ = #crate_root::env_logger::builder().is_test(true).try_init();
+        }
+    });
+
+    let reactor = reactor_tokens(&opts.reactor);
+
+    let ignore = match &opts.ignore {
+        Some((_, span)) if attrs.iter().any(|attr| attr.path.is_ident("ignore")) => {
+            return TokenStream::from(quote_spanned! { *span=>
+                compile_error!("the test already has an #[ignore] attribute");
+            });
+        }
+        Some((Some(reason), span)) => Some(quote_spanned!(*span=> #[ignore = #reason])),
+        Some((None, span)) => Some(quote_spanned!(*span=> #[ignore])),
+        None => None,
+    };
+
+    // The runner checks the outcome of every case itself.
+    let ret = match &proptest {
+        Some(_) => quote!(),
+        None => ret,
+    };
+
+    let mut tests = Vec::new();
+    for (name, threads) in &variants {
+        let run = if opts.local.is_some() {
+            quote! {
+                #crate_root::Builder::new()
+                    .local(true)
+                    #reactor
+                    .build_block_on(async #body)
+            }
+        } else if let Some(threads) = threads {
+            let threads = threads_tokens(threads, crate_root);
+            let workers = workers_tokens(opts);
+            quote! {
+                #crate_root::Builder::new()
+                    .threads(#threads)
+                    #workers
+                    #reactor
+                    .executor(#crate_root::__private::global_executor())
+                    .build_block_on(async #body)
+            }
+        } else if reactor.is_some() {
+            // Only the builder can spawn the reactor thread, so the test drives the executor alone.
+            quote! {
+                #crate_root::Builder::new()
+                    .threads(0)
+                    #reactor
+                    .executor(#crate_root::__private::global_executor())
+                    .build_block_on(async #body)
+            }
+        } else {
+            quote! {
+                #crate_root::block_on(async #body)
+            }
+        };
+
+        let run = match opts.isolated {
+            Some(_) => quote!(#crate_root::__private::isolated(move || #run)),
+            None => run,
+        };
+
+        // Every case blocks on the runtime again, and the runner reports the failing case it
+        // shrank down to.
+        let run = match &proptest {
+            Some((strategies, cases)) => {
+                let config = internal_ident("config");
+                let runner = internal_ident("runner");
+                let err = internal_ident("err");
+                let cases = cases.map(|cases| quote!(#config.cases = #cases;));
+                quote! {
+                    let mut #config = #crate_root::proptest::test_runner::Config::default();
+                    #cases
+                    #config.test_name = #crate_root::std::option::Option::Some(
+                        #crate_root::std::concat!(
+                            #crate_root::std::module_path!(),
+                            "::",
+                            #crate_root::std::stringify!(#name),
+                        ),
+                    );
+                    #config.source_file =
+                        #crate_root::std::option::Option::Some(#crate_root::std::file!());
+                    let mut #runner = #crate_root::proptest::test_runner::TestRunner::new(#config);
+                    let result = #runner.run(&#strategies, |#proptest_values| {
+                        #crate_root::__private::ProptestOutcome::into_case_result(#run)
+                    });
+                    if let #crate_root::std::result::Result::Err(#err) = result {
+                        #crate_root::std::panic!("{}\n{}", #err, #runner);
+                    }
+                }
+            }
+            None => run,
+        };
+
+        let run = match opts.retries {
+            Some((retries, _)) => {
+                let attempts = retries + 1;
+                let attempt = internal_ident("attempt");
+                quote! {
+                    let mut #attempt: u32 = 1;
+                    loop {
+                        let result = #crate_root::std::panic::catch_unwind(
+                            #crate_root::std::panic::AssertUnwindSafe(|| #run),
+                        );
+                        match result {
+                            #crate_root::std::result::Result::Ok(output)
+                                if #attempt == #attempts
+                                    || !#crate_root::__private::TestOutcome::is_failure(&output) =>
+                            {
+                                return output;
+                            }
+                            #crate_root::std::result::Result::Err(panic) if #attempt == #attempts => {
+                                #crate_root::std::panic::resume_unwind(panic);
+                            }
+                            _ => {
+                                #crate_root::std::eprintln!(
+                                    "test '{}' failed on attempt {} of {}, retrying",
+                                    #crate_root::std::stringify!(#name),
+                                    #attempt,
+                                    #attempts,
+                                );
+                                #attempt += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            None => run,
+        };
+
+        // `#[test]` comes first and the other attributes follow in their original order, wherever
+        // they were relative to the macro, so libtest sees `#[should_panic]` and friends.
+        tests.push(quote! {
+            #[test]
+            #ignore
+            #(#attrs)*
+            fn #name #lifetimes() #ret #where_clause {
+                #init_tracing
+                #init_env_logger
+                #run
+            }
+        });
+    }
+
+    let result = quote! {
+        #(#tests)*
+    };
+
+    with_crate_root(opts, result).into()
+}
+
+/// Takes the inputs of a proptest out of its signature and binds them at the start of the body,
+/// from the tuple `values` generated by the returned strategy.
+///
+/// Each input is generated by `any` of its type, or by the strategy given with
+/// `#[strategy(..)]`.
+fn proptest_strategies(
+    input: &mut syn::ItemFn,
+    crate_root: &syn::Path,
+    values: &syn::Ident,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if !input.sig.generics.params.is_empty() || input.sig.generics.where_clause.is_some() {
+        return Err(syn::Error::new_spanned(
+            &input.sig.generics,
+            "proptest tests cannot be generic",
+        ));
+    }
+
+    let mut pats = Vec::new();
+    let mut tys = Vec::new();
+    let mut strategies = Vec::new();
+    for arg in &input.sig.inputs {
+        let arg = match arg {
+            syn::FnArg::Typed(arg) => arg,
+            syn::FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "proptest tests cannot take self",
+                ))
+            }
+        };
+        let mut strategy = None;
+        for attr in &arg.attrs {
+            if !attr.path.is_ident("strategy") {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "proptest inputs only support the #[strategy(..)] attribute",
+                ));
+            }
+            if strategy.is_some() {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "multiple strategy attributes",
+                ));
+            }
+            strategy = Some(attr.parse_args::<syn::Expr>()?);
+        }
+
+        let (pat, ty) = (&arg.pat, &arg.ty);
+        strategies.push(match strategy {
+            Some(strategy) => quote!(#strategy),
+            None => quote!(#crate_root::proptest::arbitrary::any::<#ty>()),
+        });
+        pats.push(pat.clone());
+        tys.push(ty.clone());
+    }
+    if pats.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.sig,
+            "a proptest must take at least one input",
+        ));
+    }
+
+    input.block.stmts.insert(
+        0,
+        syn::parse_quote! {
+            let (#(#pats,)*): (#(#tys,)*) = #values;
+        },
+    );
+    input.sig.inputs.clear();
+    Ok(quote!((#(#strategies,)*)))
+}
+
+/// Returns `T` if the function returns an `impl Future<Output = T>`.
+fn future_output(output: &syn::ReturnType) -> Option<&syn::Type> {
+    let bounds = match output {
+        syn::ReturnType::Type(_, ty) => match &**ty {
+            syn::Type::ImplTrait(ty) => &ty.bounds,
+            _ => return None,
+        },
+        syn::ReturnType::Default => return None,
+    };
+    bounds.iter().find_map(|bound| {
+        let segment = match bound {
+            syn::TypeParamBound::Trait(bound) => bound.path.segments.last()?,
+            syn::TypeParamBound::Lifetime(_) => return None,
+        };
+        if segment.ident != "Future" {
+            return None;
+        }
+        match &segment.arguments {
+            syn::PathArguments::AngleBracketed(args) => {
+                args.args.iter().find_map(|arg| match arg {
+                    syn::GenericArgument::Binding(binding) if binding.ident == "Output" => {
+                        Some(&binding.ty)
+                    }
+                    _ => None,
+                })
+            }
+            _ => None,
+        }
+    })
+}
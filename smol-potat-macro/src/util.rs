@@ -0,0 +1,242 @@
+//! Helpers shared by the expansions of the attributes.
+
+use proc_macro2::Span;
+use quote::{quote, quote_spanned, ToTokens};
+use syn::spanned::Spanned;
+
+use crate::opts::{Opts, Panic, Reactor, Threads};
+
+/// Returns the body of a test or benchmark as a block.
+///
+/// Test functions can't be generic, so a generic function is turned into a generic inner function
+/// which the block calls with the defaults of its generic parameters.
+pub(crate) fn monomorphize(input: &syn::ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.sig.ident;
+    let ret = &input.sig.output;
+    let body = &input.block;
+
+    if input.sig.generics.params.is_empty() && input.sig.generics.where_clause.is_none() {
+        return Ok(quote!(#body));
+    }
+
+    let mut generics = input.sig.generics.clone();
+    let mut args = Vec::new();
+    for param in &mut generics.params {
+        match param {
+            syn::GenericParam::Type(param) => match param.default.take() {
+                Some(default) => {
+                    param.eq_token = None;
+                    args.push(quote!(#default));
+                }
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        &param.ident,
+                        "generic parameters must have a default to be used in an async test",
+                    ))
+                }
+            },
+            syn::GenericParam::Const(param) => match param.default.take() {
+                Some(default) => {
+                    param.eq_token = None;
+                    args.push(quote!({ #default }));
+                }
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        &param.ident,
+                        "generic parameters must have a default to be used in an async test",
+                    ))
+                }
+            },
+            syn::GenericParam::Lifetime(_) => {}
+        }
+    }
+    let (generics, _, where_clause) = generics.split_for_impl();
+    let inner = quote::format_ident!("__smol_potat_{}", name);
+
+    Ok(quote! {
+        {
+            async fn #inner #generics() #ret #where_clause #body
+
+            #inner::<#(#args),*>().await
+        }
+    })
+}
+
+/// Returns the lifetime parameters of a test or benchmark and its `where` clause, which the function
+/// generated for the harness keeps so that its signature can name the lifetimes.
+///
+/// Predicates naming a type or const parameter only apply to the inner function built by
+/// `monomorphize`, as the generated function doesn't have those parameters.
+pub(crate) fn wrapper_generics(
+    generics: &syn::Generics,
+) -> (proc_macro2::TokenStream, Option<proc_macro2::TokenStream>) {
+    let lifetimes: Vec<_> = generics.lifetimes().collect();
+    let lifetimes = match lifetimes.is_empty() {
+        true => quote!(),
+        false => quote!(<#(#lifetimes),*>),
+    };
+
+    let params: Vec<_> = generics
+        .type_params()
+        .map(|param| &param.ident)
+        .chain(generics.const_params().map(|param| &param.ident))
+        .collect();
+    let predicates: Vec<_> = generics
+        .where_clause
+        .iter()
+        .flat_map(|clause| &clause.predicates)
+        .filter(|predicate| !names_any(predicate.to_token_stream(), &params))
+        .collect();
+    let where_clause = match predicates.is_empty() {
+        true => None,
+        false => Some(quote!(where #(#predicates),*)),
+    };
+    (lifetimes, where_clause)
+}
+
+/// Returns whether `tokens` contain one of `idents`, in any group.
+fn names_any(tokens: proc_macro2::TokenStream, idents: &[&syn::Ident]) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => idents.iter().any(|param| **param == ident),
+        proc_macro2::TokenTree::Group(group) => names_any(group.stream(), idents),
+        _ => false,
+    })
+}
+
+/// Returns the return type and `T` if the function returns a `Result<T, ..>`, including aliases
+/// like `io::Result<T>`.
+pub(crate) fn result_ok_type(output: &syn::ReturnType) -> Option<(&syn::Type, &syn::Type)> {
+    let ty = match output {
+        syn::ReturnType::Type(_, ty) => ty,
+        syn::ReturnType::Default => return None,
+    };
+    let segment = match &**ty {
+        syn::Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident != "Result" {
+        return None;
+    }
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+            syn::GenericArgument::Type(ok) => Some((ty, ok)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Replaces an explicit `-> ()` with the default return type, so that both forms expand to the
+/// same code.
+pub(crate) fn normalize_unit(output: &mut syn::ReturnType) {
+    if let syn::ReturnType::Type(_, ty) = output {
+        if matches!(&**ty, syn::Type::Tuple(tuple) if tuple.elems.is_empty()) {
+            *output = syn::ReturnType::Default;
+        }
+    }
+}
+
+/// Returns an expression evaluating to the number of worker threads as a `usize`.
+pub(crate) fn threads_tokens(
+    threads: &(Threads, Span),
+    crate_root: &syn::Path,
+) -> proc_macro2::TokenStream {
+    match threads {
+        (Threads::Num(num), span) => {
+            let num = *num as usize;
+            quote_spanned!(*span=> #num)
+        }
+        (Threads::Auto, _) => quote! {
+            #crate_root::std::cmp::max(#crate_root::__private::num_cpus::get(), 1)
+        },
+        (Threads::Env, _) => {
+            let default = default_threads_tokens(crate_root);
+            quote! {
+                #crate_root::__private::env_threads().unwrap_or_else(|| #default)
+            }
+        }
+        (Threads::Container, _) => quote! {
+            #crate_root::__private::container_threads()
+        },
+        (Threads::Expr(expr), span) => quote_spanned! { *span=>
+            #crate_root::std::convert::TryFrom::try_from(#expr)
+                .expect("threads argument must be a valid number of threads")
+        },
+        (Threads::Fn(threads_fn), span) => {
+            let call = internal_ident("threads_fn");
+            quote_spanned! { *span=>
+                {
+                    let #call: fn() -> usize = #threads_fn;
+                    #call()
+                }
+            }
+        }
+    }
+}
+
+/// Returns the builder method calls configuring the worker threads.
+pub(crate) fn workers_tokens(opts: &Opts) -> proc_macro2::TokenStream {
+    let thread_name = opts
+        .thread_name
+        .as_ref()
+        .map(|(name, _)| quote!(.thread_name(#name)));
+    let stack_size = opts
+        .stack_size
+        .map(|(bytes, _)| quote!(.stack_size(#bytes)));
+    let panic = match opts.panic {
+        Some((Panic::Abort, span)) => Some(quote_spanned!(span=> .abort_on_panic(true))),
+        Some((Panic::Propagate, span)) => Some(quote_spanned!(span=> .propagate_panics(true))),
+        Some((Panic::Continue, _)) | None => None,
+    };
+    let on_thread_start = opts
+        .on_thread_start
+        .as_ref()
+        .map(|(f, span)| quote_spanned!(*span=> .on_thread_start(#f)));
+    let pin_threads = opts
+        .pin_threads
+        .map(|span| quote_spanned!(span=> .pin_threads(true)));
+    quote!(#thread_name #stack_size #panic #on_thread_start #pin_threads)
+}
+
+/// Returns the builder method call enabling a dedicated reactor thread, if one was asked for.
+pub(crate) fn reactor_tokens(
+    reactor: &Option<(Reactor, Span)>,
+) -> Option<proc_macro2::TokenStream> {
+    match reactor {
+        Some((Reactor::Dedicated, span)) => Some(quote_spanned!(*span=> .dedicated_reactor(true))),
+        Some((Reactor::Shared, _)) | None => None,
+    }
+}
+
+/// Returns an identifier for a local variable of the generated code, which the function body can't
+/// see or shadow.
+pub(crate) fn internal_ident(name: &str) -> syn::Ident {
+    syn::Ident::new(name, Span::mixed_site())
+}
+
+/// Wraps the generated code in a macro reached through a custom crate root, so that a path that
+/// doesn't lead to smol-potat fails to resolve once, at the `crate` argument, instead of at every
+/// path in the generated code.
+pub(crate) fn with_crate_root(
+    opts: &Opts,
+    tokens: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    if !opts.custom_crate_root {
+        return tokens;
+    }
+    let crate_root = &opts.crate_root;
+    quote_spanned! { crate_root.span()=>
+        #crate_root::__private::with_crate_root! {
+            #tokens
+        }
+    }
+}
+
+/// Returns the number of worker threads used when the `threads` option isn't given.
+pub(crate) fn default_threads_tokens(crate_root: &syn::Path) -> proc_macro2::TokenStream {
+    if cfg!(feature = "auto") {
+        threads_tokens(&(Threads::Auto, Span::call_site()), crate_root)
+    } else {
+        quote!(1)
+    }
+}
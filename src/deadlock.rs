@@ -0,0 +1,103 @@
+//! Progress tracking for tests with `deadlock_detect`.
+//!
+//! The executor doesn't tell whether its tasks are making progress, so this counts the wake-ups
+//! of the test's own future, on top of the polls of the tasks it spawned with
+//! [`spawn`](crate::spawn), directly or from those tasks. When neither changes for the window
+//! given to `deadlock_detect`, the test is considered deadlocked.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+
+use async_io::Timer;
+use futures_lite::future;
+
+use crate::tasks::{Progress, Watched};
+
+/// How often progress is checked, unless the window is shorter.
+const CHECK: Duration = Duration::from_millis(100);
+
+/// Forwards wake-ups and counts them.
+struct Counter {
+    wakes: Arc<AtomicUsize>,
+    waker: Waker,
+}
+
+impl Wake for Counter {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::Relaxed);
+        self.waker.wake_by_ref();
+    }
+}
+
+/// Counts the wake-ups of a future, which happen whenever something it waits on is ready.
+struct Woken<F> {
+    future: Pin<Box<F>>,
+    wakes: Arc<AtomicUsize>,
+}
+
+impl<F: Future> Future for Woken<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = &mut *self;
+        let waker = Waker::from(Arc::new(Counter {
+            wakes: this.wakes.clone(),
+            waker: cx.waker().clone(),
+        }));
+        this.future.as_mut().poll(&mut Context::from_waker(&waker))
+    }
+}
+
+/// Runs the test's future, panicking if neither it nor any of its tasks makes progress for
+/// `window`.
+pub(crate) async fn detect<F: Future>(
+    name: &'static str,
+    window: Duration,
+    future: F,
+) -> F::Output {
+    let wakes = Arc::new(AtomicUsize::new(0));
+    let tasks = Arc::new(Progress::default());
+    let progress = {
+        let wakes = wakes.clone();
+        let tasks = tasks.clone();
+        move || wakes.load(Ordering::Relaxed).wrapping_add(tasks.polls())
+    };
+
+    let future = Woken {
+        future: Box::pin(Watched::new(tasks.clone(), future)),
+        wakes,
+    };
+    let check = CHECK.min(window);
+    let detector = async {
+        let mut last = progress();
+        let mut stalled = Duration::ZERO;
+        loop {
+            Timer::after(check).await;
+            let current = progress();
+            if current != last {
+                last = current;
+                stalled = Duration::ZERO;
+                continue;
+            }
+
+            stalled += check;
+            if stalled >= window {
+                panic!(
+                    "test '{}' deadlocked: no progress for {}ms, with {} spawned tasks pending",
+                    name,
+                    window.as_millis(),
+                    tasks.pending(),
+                );
+            }
+        }
+    };
+    future::or(future, detector).await
+}
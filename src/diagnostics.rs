@@ -0,0 +1,111 @@
+//! A registry of the tasks spawned with [`spawn`](crate::spawn), for debugging stuck tasks.
+//!
+//! With the `diagnostics` feature, every such task is registered while it is pending, so
+//! [`dump_tasks`] can list them along with what they are doing.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// What a task spawned with [`spawn`](crate::spawn) is doing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskState {
+    /// The task is being polled.
+    Running,
+    /// The task is waiting to be woken.
+    Waiting,
+}
+
+const RUNNING: u8 = 0;
+const WAITING: u8 = 1;
+
+/// The pending tasks, by the order they were spawned in.
+static TASKS: Mutex<BTreeMap<u64, Arc<Entry>>> = Mutex::new(BTreeMap::new());
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+struct Entry {
+    name: String,
+    state: AtomicU8,
+}
+
+/// Returns the name and state of every pending task spawned with [`spawn`](crate::spawn), in the
+/// order they were spawned in.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[smol_potat::main]
+/// async fn main() {
+///     let task = smol_potat::spawn(smol::future::pending::<()>());
+///     smol::future::yield_now().await;
+///     assert_eq!(
+///         smol_potat::dump_tasks(),
+///         [("<unnamed>".to_string(), smol_potat::TaskState::Waiting)],
+///     );
+/// }
+/// ```
+pub fn dump_tasks() -> Vec<(String, TaskState)> {
+    TASKS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|entry| {
+            let state = match entry.state.load(Ordering::Relaxed) {
+                RUNNING => TaskState::Running,
+                _ => TaskState::Waiting,
+            };
+            (entry.name.clone(), state)
+        })
+        .collect()
+}
+
+/// Prints the pending tasks to stderr.
+#[cfg(all(unix, feature = "async-signal", not(feature = "ctrlc-integration")))]
+pub(crate) fn print_tasks() {
+    let tasks = dump_tasks();
+    eprintln!("task dump: {} pending tasks", tasks.len());
+    for (name, state) in tasks {
+        eprintln!("  {}: {:?}", name, state);
+    }
+}
+
+/// Keeps a task in the registry until it is dropped.
+pub(crate) struct Registered {
+    id: u64,
+    entry: Arc<Entry>,
+}
+
+impl Registered {
+    pub(crate) fn new(name: String) -> Registered {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let entry = Arc::new(Entry {
+            name,
+            state: AtomicU8::new(WAITING),
+        });
+        TASKS.lock().unwrap().insert(id, entry.clone());
+        Registered { id, entry }
+    }
+
+    /// Marks the task as running while it is polled.
+    pub(crate) fn poll<T>(&self, poll: impl FnOnce() -> T) -> T {
+        /// Marks the task as waiting again, even if the poll panics.
+        struct Waiting<'a>(&'a Entry);
+
+        impl Drop for Waiting<'_> {
+            fn drop(&mut self) {
+                self.0.state.store(WAITING, Ordering::Relaxed);
+            }
+        }
+
+        self.entry.state.store(RUNNING, Ordering::Relaxed);
+        let _waiting = Waiting(&self.entry);
+        poll()
+    }
+}
+
+impl Drop for Registered {
+    fn drop(&mut self) {
+        TASKS.lock().unwrap().remove(&self.id);
+    }
+}
@@ -1,10 +1,1227 @@
 //! Attribute macros for [`smol`](https://github.com/stjepang/smol).
+//!
+//! # Custom crate root
+//!
+//! The code generated by the macros refers to this crate by the path given with the `crate`
+//! option, `::smol_potat` by default. A crate wrapping smol-potat can re-export all of it and
+//! point the option at the re-export:
+//!
+//! ```
+//! mod runtime {
+//!     pub use smol_potat::*;
+//! }
+//!
+//! #[smol_potat::main(crate = runtime, entry = false)]
+//! async fn run() -> u32 {
+//!     1 + 2
+//! }
+//!
+//! fn main() {
+//!     assert_eq!(run(), 3);
+//! }
+//! ```
+//!
+//! The generated code also uses items that are not part of the documented API, so re-exporting
+//! only some of them is not enough. The whole generated code is reached through the crate root,
+//! so such a root is reported once, as an unresolved path at the `crate` argument:
+//!
+//! ```compile_fail
+//! mod runtime {
+//!     pub use smol_potat::{block_on, Builder};
+//! }
+//!
+//! #[smol_potat::main(crate = runtime, entry = false)]
+//! async fn run() -> u32 {
+//!     1 + 2
+//! }
+//! # fn main() {}
+//! ```
 
+#[cfg(feature = "compat")]
+#[doc(hidden)]
+pub use async_compat;
+#[doc(hidden)]
+pub use async_executor;
+#[cfg(feature = "global-executor")]
+#[doc(hidden)]
+pub use async_global_executor;
 #[doc(hidden)]
 pub use async_io;
+#[doc(hidden)]
+pub use async_lock;
+#[cfg(feature = "clap")]
+#[doc(hidden)]
+pub use clap;
+#[cfg(feature = "criterion")]
+#[doc(hidden)]
+pub use criterion;
+#[cfg(feature = "ctrlc-integration")]
+#[doc(hidden)]
+pub use ctrlc;
+#[cfg(feature = "divan")]
+#[doc(hidden)]
+pub use divan;
+#[cfg(feature = "env_logger")]
+#[doc(hidden)]
+pub use env_logger;
+#[doc(hidden)]
+pub use fastrand;
+#[doc(hidden)]
+pub use futures_lite;
 #[cfg(feature = "auto")]
 pub use num_cpus;
+#[cfg(feature = "proptest")]
+#[doc(hidden)]
+pub use proptest;
+#[cfg(feature = "smol-block-on")]
+#[doc(hidden)]
+pub use smol;
 #[doc(hidden)]
 pub use std;
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing_subscriber;
+
+/// Blocks the current thread on a future.
+///
+/// This is the same `block_on` the attribute macros and [`Builder`] use, so helpers calling it are
+/// guaranteed to run on the same version of `async-io` as the macro-annotated entry points.
+///
+/// With the `smol-block-on` feature, this is `smol::block_on` instead of `async_io::block_on`.
+/// smol 1.x re-exports the `async-io` one, so the behavior is the same, but the function then
+/// comes from the version of `smol` the rest of the program resolves to, and moves along with it.
+///
+/// # Examples
+///
+/// ```
+/// let sum = smol_potat::block_on(async { 1 + 2 });
+/// assert_eq!(sum, 3);
+/// ```
+#[cfg(not(feature = "smol-block-on"))]
+pub use async_io::block_on;
+#[cfg(feature = "smol-block-on")]
+pub use smol::block_on;
+
+pub use async_executor::{Executor, Task};
 
 pub use smol_potat_macro::{bench, main, test};
+
+#[cfg(feature = "diagnostics")]
+pub use diagnostics::{dump_tasks, TaskState};
+
+/// Expands to the code generated for a custom `crate` root, see `__private::with_crate_root`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __smol_potat_with_crate_root {
+    ($($item:tt)*) => {
+        $($item)*
+    };
+}
+
+#[cfg(feature = "log")]
+pub mod logs;
+pub mod time;
+
+mod deadlock;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+mod tasks;
+mod watchdog;
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::Future;
+use std::panic;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, OnceLock};
+use std::thread;
+
+use async_executor::LocalExecutor;
+
+static GLOBAL_EXECUTOR: OnceLock<Arc<Executor<'static>>> = OnceLock::new();
+
+/// The number of runtimes driving `GLOBAL_EXECUTOR` at the moment.
+static RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the executor that `spawn` spawns onto, if a runtime is driving it.
+fn running_executor() -> Option<&'static Arc<Executor<'static>>> {
+    GLOBAL_EXECUTOR
+        .get()
+        .filter(|_| RUNNING.load(Ordering::SeqCst) > 0)
+}
+
+/// Counts a runtime as driving `GLOBAL_EXECUTOR` until it is dropped.
+struct Running;
+
+impl Running {
+    fn new() -> Running {
+        RUNNING.fetch_add(1, Ordering::SeqCst);
+        Running
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Spawns a task onto the executor run by [`main`], or by a [`test`] with worker threads.
+///
+/// All of these share one process-wide executor, so tasks can be spawned from anywhere without
+/// passing a handle around. The task is cancelled if the returned [`Task`] is dropped, unless it
+/// is detached.
+///
+/// # Panics
+///
+/// Panics unless one of those entry points is running, since nothing would run the task. A
+/// `local` main or test doesn't count, as its executor can't run tasks on other threads, and
+/// neither does a test without worker threads.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[smol_potat::main]
+/// async fn main() {
+///     let task = smol_potat::spawn(async { 1 + 2 });
+///     assert_eq!(task.await, 3);
+/// }
+/// ```
+pub fn spawn<T: Send + 'static>(future: impl Future<Output = T> + Send + 'static) -> Task<T> {
+    let ex = running_executor().expect(
+        "smol_potat::spawn must be called from a #[smol_potat::main] or a #[smol_potat::test] \
+         with threads",
+    );
+    ex.spawn(tasks::Tracked::new(None, future))
+}
+
+/// Spawns a task like [`spawn`], with a name identifying it in diagnostics.
+///
+/// With the `diagnostics` feature, the name is what [`dump_tasks`] and the dumps printed on
+/// `SIGQUIT` by `#[smol_potat::main(diagnostics)]` list the task as. Tasks spawned with [`spawn`]
+/// are listed as `<unnamed>`. Without the feature, the name is not kept.
+///
+/// # Panics
+///
+/// Panics in the same cases as [`spawn`].
+///
+/// # Examples
+///
+/// ```ignore
+/// #[smol_potat::main]
+/// async fn main() {
+///     let task = smol_potat::spawn_named("fetch", async { 1 + 2 });
+///     assert_eq!(task.await, 3);
+/// }
+/// ```
+///
+/// [`dump_tasks`]: https://docs.rs/smol-potat/latest/smol_potat/fn.dump_tasks.html
+pub fn spawn_named<T: Send + 'static>(
+    name: impl Into<String>,
+    future: impl Future<Output = T> + Send + 'static,
+) -> Task<T> {
+    let ex = running_executor().expect(
+        "smol_potat::spawn_named must be called from a #[smol_potat::main] or a \
+         #[smol_potat::test] with threads",
+    );
+    ex.spawn(tasks::Tracked::new(Some(name.into()), future))
+}
+
+/// Closed when a termination signal is caught, which wakes every receiver at once.
+static SHUTDOWN: OnceLock<(async_channel::Sender<()>, async_channel::Receiver<()>)> =
+    OnceLock::new();
+
+fn shutdown_channel() -> &'static (async_channel::Sender<()>, async_channel::Receiver<()>) {
+    SHUTDOWN.get_or_init(|| async_channel::bounded(1))
+}
+
+/// Completes once a [`main`] with `catch_signals` has received a termination signal.
+///
+/// With `shutdown_grace_ms`, main keeps running for that long after the signal, so the body and
+/// its tasks can use this to stop accepting work and finish what is in flight. The future can
+/// be created before or after the signal, and any number of them can wait at once.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[smol_potat::main(catch_signals, shutdown_grace_ms = 3000)]
+/// async fn main() {
+///     let server = smol_potat::spawn(serve());
+///     smol_potat::shutdown_signal().await;
+///     // up to 3 seconds to drain connections before main is dropped
+///     server.await;
+/// }
+/// ```
+pub fn shutdown_signal() -> impl Future<Output = ()> + Send + 'static {
+    let receiver = shutdown_channel().1.clone();
+    async move {
+        let _ = receiver.recv().await;
+    }
+}
+
+/// How many times in a row [`idle`] has to find nothing to run before it gives up on the tasks
+/// that are left.
+const IDLE_ROUNDS: u32 = 64;
+
+/// Completes once the executor that [`spawn`] spawns onto has run every task that is ready to
+/// run.
+///
+/// This lets a test wait for the effects of the tasks it spawned instead of sleeping for an
+/// arbitrary amount of time. Runnable tasks are run on the current thread until there are none
+/// left and every task has finished, or until nothing has been runnable for a while, since tasks
+/// waiting on I/O or timers are never ready.
+///
+/// It is best-effort, as the executor doesn't report what its worker threads are doing: a task
+/// that is busy on another worker, or that is woken by an I/O event after `idle` has given up,
+/// isn't waited for. Without an executor, as in a test without worker threads, this only yields
+/// once.
+///
+/// # Examples
+///
+/// ```ignore
+/// #[smol_potat::test(threads = 2)]
+/// async fn flushes_in_the_background() {
+///     let (sender, receiver) = smol::channel::unbounded();
+///     smol_potat::spawn(async move { sender.send(1).await }).detach();
+///     smol_potat::idle().await;
+///     assert_eq!(receiver.try_recv(), Ok(1));
+/// }
+/// ```
+pub async fn idle() {
+    let ex = match GLOBAL_EXECUTOR.get() {
+        Some(ex) => ex,
+        None => return futures_lite::future::yield_now().await,
+    };
+
+    let mut rounds = 0;
+    while !ex.is_empty() && rounds < IDLE_ROUNDS {
+        if ex.try_tick() {
+            rounds = 0;
+        } else {
+            // Give the worker threads, and tasks woken by them, a chance to run.
+            rounds += 1;
+            futures_lite::future::yield_now().await;
+        }
+    }
+}
+
+/// Configures and runs the runtime that the [`main`] macro sets up, for programs that decide
+/// how to run only after they've started.
+///
+/// # Examples
+///
+/// ```
+/// let output = smol_potat::Builder::new()
+///     .threads(2)
+///     .build_block_on(async { 1 + 2 });
+/// assert_eq!(output, 3);
+/// ```
+pub struct Builder {
+    threads: usize,
+    local: bool,
+    executor: Option<Arc<Executor<'static>>>,
+    thread_name: Option<String>,
+    stack_size: Option<usize>,
+    dedicated_reactor: bool,
+    abort_on_panic: bool,
+    propagate_panics: bool,
+    on_thread_start: Option<Arc<dyn Fn() + Send + Sync>>,
+    #[cfg(feature = "core_affinity")]
+    pin_threads: bool,
+}
+
+impl Builder {
+    /// Creates a builder for a runtime with a single worker thread.
+    pub fn new() -> Builder {
+        Builder {
+            threads: 1,
+            local: false,
+            executor: None,
+            thread_name: None,
+            stack_size: None,
+            dedicated_reactor: false,
+            abort_on_panic: false,
+            propagate_panics: false,
+            on_thread_start: None,
+            #[cfg(feature = "core_affinity")]
+            pin_threads: false,
+        }
+    }
+
+    /// Sets the number of worker threads driving the executor.
+    ///
+    /// The thread calling [`build_block_on`](Builder::build_block_on) drives the executor too.
+    pub fn threads(mut self, threads: usize) -> Builder {
+        self.threads = threads;
+        self
+    }
+
+    /// Runs the future on a single-threaded `LocalExecutor` instead, so it doesn't need to be
+    /// `Send`. No worker threads are spawned in this mode.
+    pub fn local(mut self, local: bool) -> Builder {
+        self.local = local;
+        self
+    }
+
+    /// Uses the given executor, so the future can spawn tasks onto it, instead of creating a new
+    /// one. This has no effect in local mode.
+    pub fn executor(mut self, executor: Arc<Executor<'static>>) -> Builder {
+        self.executor = Some(executor);
+        self
+    }
+
+    /// Names the worker threads `{name}-{index}`, with indices starting at 0, so they can be told
+    /// apart in debuggers and panic messages.
+    pub fn thread_name(mut self, name: impl Into<String>) -> Builder {
+        self.thread_name = Some(name.into());
+        self
+    }
+
+    /// Sets the stack size of the worker threads, in bytes.
+    pub fn stack_size(mut self, stack_size: usize) -> Builder {
+        self.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Spawns a thread that does nothing but drive the I/O and timer reactor, so timers and I/O
+    /// events are processed promptly even while every executor thread is busy.
+    ///
+    /// `async-io` has a single reactor per process, so this doesn't create a separate reactor;
+    /// it dedicates a thread to driving the shared one.
+    pub fn dedicated_reactor(mut self, dedicated: bool) -> Builder {
+        self.dedicated_reactor = dedicated;
+        self
+    }
+
+    /// Aborts the process when a task panics on one of the runtime's threads. This has no effect
+    /// in local mode.
+    ///
+    /// By default the panic is reported by the panic hook and the executor catches it, so the
+    /// thread goes back to running other tasks and the panic is resumed wherever the task's
+    /// `Task` handle is awaited.
+    pub fn abort_on_panic(mut self, abort: bool) -> Builder {
+        self.abort_on_panic = abort;
+        self
+    }
+
+    /// Resumes the first panic of a task on one of the runtime's threads once the future has
+    /// completed, so it isn't lost when nothing awaits the task. This has no effect in local mode.
+    ///
+    /// Only the panic message is kept, it is resumed as a `String` payload.
+    pub fn propagate_panics(mut self, propagate: bool) -> Builder {
+        self.propagate_panics = propagate;
+        self
+    }
+
+    /// Calls `f` at the start of each worker thread, before it runs any task, to set up
+    /// thread-local state.
+    pub fn on_thread_start<F>(mut self, f: F) -> Builder
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_thread_start = Some(Arc::new(f));
+        self
+    }
+
+    /// Pins each worker thread to a CPU core, going round-robin over the cores, so the OS doesn't
+    /// migrate them. This has no effect in local mode, and where the cores or the affinity of a
+    /// thread can't be determined.
+    #[cfg(feature = "core_affinity")]
+    pub fn pin_threads(mut self, pin: bool) -> Builder {
+        self.pin_threads = pin;
+        self
+    }
+
+    /// Starts the worker threads and blocks the current thread on the future, returning its
+    /// output. The workers are shut down before this returns.
+    pub fn build_block_on<F: Future>(self, future: F) -> F::Output {
+        let ex = self.executor.unwrap_or_default();
+        let threads = self.threads;
+        let thread_name = self.thread_name;
+        let stack_size = self.stack_size;
+        let local = self.local;
+        let dedicated_reactor = self.dedicated_reactor;
+        let abort_on_panic = self.abort_on_panic;
+        let on_thread_start = self.on_thread_start;
+        #[cfg(feature = "core_affinity")]
+        let cores = match self.pin_threads {
+            true => core_affinity::get_core_ids().unwrap_or_default(),
+            false => Vec::new(),
+        };
+        let first_panic = match self.propagate_panics {
+            true => Some(Arc::new(Mutex::new(None))),
+            false => None,
+        };
+        if abort_on_panic || first_panic.is_some() {
+            install_panic_hook();
+        }
+        let _running = match GLOBAL_EXECUTOR.get() {
+            Some(global) if !local && Arc::ptr_eq(global, &ex) => Some(Running::new()),
+            _ => None,
+        };
+
+        let output = thread::scope(|scope| {
+            // Dropping `signal` closes the channel, which stops the workers. It lives in this
+            // closure so that it is also dropped if the future panics.
+            let (signal, shutdown) = async_channel::unbounded::<()>();
+
+            if dedicated_reactor {
+                let shutdown = shutdown.clone();
+                thread::Builder::new()
+                    .name("smol-potat-reactor".to_string())
+                    .spawn_scoped(scope, move || {
+                        let _ = block_on(shutdown.recv());
+                    })
+                    .expect("failed to spawn the reactor thread");
+            }
+
+            if local {
+                let ex = LocalExecutor::new();
+                let output = block_on(ex.run(future));
+                drop(signal);
+                return output;
+            }
+
+            for i in 0..threads {
+                let ex = &ex;
+                let shutdown = shutdown.clone();
+                let on_thread_start = on_thread_start.clone();
+                let first_panic = first_panic.clone();
+                #[cfg(feature = "core_affinity")]
+                let core = match cores.len() {
+                    0 => None,
+                    len => Some(cores[i % len]),
+                };
+                let mut builder = thread::Builder::new();
+                if let Some(name) = &thread_name {
+                    builder = builder.name(format!("{}-{}", name, i));
+                }
+                if let Some(stack_size) = stack_size {
+                    builder = builder.stack_size(stack_size);
+                }
+                builder
+                    .spawn_scoped(scope, move || {
+                        #[cfg(feature = "core_affinity")]
+                        if let Some(core) = core {
+                            core_affinity::set_for_current(core);
+                        }
+                        ABORT_ON_PANIC.with(|abort| abort.set(abort_on_panic));
+                        FIRST_PANIC.with(|slot| slot.replace(first_panic));
+                        if let Some(on_thread_start) = on_thread_start {
+                            on_thread_start();
+                        }
+                        let _ = block_on(ex.run(shutdown.recv()));
+                    })
+                    .expect("failed to spawn a worker thread");
+            }
+
+            let output = {
+                let _abort = AbortOnPanic::enter(abort_on_panic);
+                let _record = RecordPanics::enter(first_panic.clone());
+                block_on(ex.run(future))
+            };
+            drop(signal);
+            output
+        });
+
+        // The workers have been joined, so no panic can be recorded anymore.
+        let first_panic = first_panic.and_then(|slot| slot.lock().unwrap().take());
+        if let Some(message) = first_panic {
+            panic::resume_unwind(Box::new(message));
+        }
+        output
+    }
+}
+
+/// The message of the first panic on a runtime built with [`Builder::propagate_panics`], or of
+/// the tasks spawned by a test that propagates their panics.
+pub(crate) type FirstPanic = Arc<Mutex<Option<String>>>;
+
+thread_local! {
+    static ABORT_ON_PANIC: Cell<bool> = const { Cell::new(false) };
+    static FIRST_PANIC: RefCell<Option<FirstPanic>> = const { RefCell::new(None) };
+}
+
+/// Installs a panic hook that, after reporting a panic on the threads of a runtime, aborts the
+/// process for [`Builder::abort_on_panic`] or records the panic for [`Builder::propagate_panics`].
+/// Tasks' panics are caught by the executor, so a hook is the only place to see them.
+fn install_panic_hook() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            if ABORT_ON_PANIC.with(Cell::get) {
+                process::abort();
+            }
+            if let Some(slot) = FIRST_PANIC.with(|slot| slot.borrow().clone()) {
+                slot.lock()
+                    .unwrap()
+                    .get_or_insert_with(|| panic_message(info.payload()));
+            }
+        }));
+    });
+}
+
+/// Returns the message of a panic, as a `String` whether it was raised with one or a `&str`.
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match (
+        payload.downcast_ref::<&str>(),
+        payload.downcast_ref::<String>(),
+    ) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "Box<dyn Any>".to_string(),
+    }
+}
+
+/// Makes panics on the current thread abort the process until it is dropped.
+struct AbortOnPanic(bool);
+
+impl AbortOnPanic {
+    fn enter(abort: bool) -> AbortOnPanic {
+        AbortOnPanic(ABORT_ON_PANIC.with(|current| current.replace(abort)))
+    }
+}
+
+impl Drop for AbortOnPanic {
+    fn drop(&mut self) {
+        ABORT_ON_PANIC.with(|current| current.set(self.0));
+    }
+}
+
+/// Records panics on the current thread in the given slot until it is dropped.
+struct RecordPanics(Option<FirstPanic>);
+
+impl RecordPanics {
+    fn enter(slot: Option<FirstPanic>) -> RecordPanics {
+        RecordPanics(FIRST_PANIC.with(|current| current.replace(slot)))
+    }
+}
+
+impl Drop for RecordPanics {
+    fn drop(&mut self) {
+        FIRST_PANIC.with(|current| current.replace(self.0.take()));
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut builder = f.debug_struct("Builder");
+        builder
+            .field("threads", &self.threads)
+            .field("local", &self.local)
+            .field("thread_name", &self.thread_name)
+            .field("stack_size", &self.stack_size)
+            .field("dedicated_reactor", &self.dedicated_reactor)
+            .field("abort_on_panic", &self.abort_on_panic)
+            .field("propagate_panics", &self.propagate_panics)
+            .field("on_thread_start", &self.on_thread_start.is_some());
+        #[cfg(feature = "core_affinity")]
+        builder.field("pin_threads", &self.pin_threads);
+        builder.finish()
+    }
+}
+
+/// An executor with worker threads that live as long as the runtime, for programs that want to
+/// block on several futures without the [`main`] macro.
+///
+/// The worker threads are stopped and joined when the runtime is dropped. Tasks that are still
+/// running are dropped with it.
+///
+/// # Examples
+///
+/// ```
+/// let rt = smol_potat::Runtime::with_threads(2);
+/// let task = rt.spawn(async { 1 + 2 });
+/// assert_eq!(rt.block_on(task), 3);
+/// assert_eq!(rt.block_on(async { 4 }), 4);
+/// ```
+pub struct Runtime {
+    ex: Arc<Executor<'static>>,
+    stop: Option<async_channel::Sender<()>>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl Runtime {
+    /// Creates a runtime without worker threads, whose tasks only run on the thread blocking on
+    /// a future.
+    pub fn new() -> Runtime {
+        Runtime::with_threads(0)
+    }
+
+    /// Creates a runtime with `threads` worker threads.
+    pub fn with_threads(threads: usize) -> Runtime {
+        Runtime::start(Arc::new(Executor::new()), threads)
+    }
+
+    fn start(ex: Arc<Executor<'static>>, threads: usize) -> Runtime {
+        let (stop, stopped) = async_channel::bounded::<()>(1);
+        let threads = (0..threads)
+            .map(|i| {
+                let ex = ex.clone();
+                let stopped = stopped.clone();
+                thread::Builder::new()
+                    .name(format!("smol-potat-{}", i))
+                    .spawn(move || {
+                        let _ = block_on(ex.run(stopped.recv()));
+                    })
+                    .expect("failed to spawn a worker thread")
+            })
+            .collect();
+        Runtime {
+            ex,
+            stop: Some(stop),
+            threads,
+        }
+    }
+
+    /// Blocks the current thread on the future, running the runtime's tasks alongside it, and
+    /// returns its output.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        block_on(self.ex.run(future))
+    }
+
+    /// Spawns a task onto the runtime.
+    pub fn spawn<T: Send + 'static>(
+        &self,
+        future: impl Future<Output = T> + Send + 'static,
+    ) -> Task<T> {
+        self.ex.spawn(future)
+    }
+
+    /// Returns the executor the runtime's threads are running.
+    pub fn executor(&self) -> &Arc<Executor<'static>> {
+        &self.ex
+    }
+}
+
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime::new()
+    }
+}
+
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        // Closing the channel wakes every worker.
+        self.stop.take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl fmt::Debug for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Runtime")
+            .field("threads", &self.threads.len())
+            .finish()
+    }
+}
+
+#[doc(hidden)]
+pub mod __private {
+    use std::error::Error;
+    use std::future::Future;
+    use std::process::ExitCode;
+    use std::sync::Arc;
+
+    use std::time::Duration;
+
+    use async_executor::Executor;
+
+    pub use num_cpus;
+
+    /// Runs the future until it completes or the process receives a termination signal, in which
+    /// case [`shutdown_signal`](crate::shutdown_signal) completes and the future gets `grace` to
+    /// finish. If it doesn't, or another signal arrives, the future is dropped and `None` is
+    /// returned.
+    ///
+    /// The signal handlers are registered before this returns, so no signal is missed.
+    #[cfg(any(feature = "async-signal", feature = "ctrlc-integration"))]
+    pub fn until_signal<F: Future>(
+        future: F,
+        grace: Duration,
+    ) -> impl Future<Output = Option<F::Output>> {
+        use std::time::Instant;
+
+        use async_io::Timer;
+        #[cfg(all(feature = "async-signal", not(feature = "ctrlc-integration")))]
+        use async_signal::{Signal, Signals};
+        use futures_lite::{future, StreamExt};
+
+        #[cfg(all(feature = "async-signal", not(feature = "ctrlc-integration")))]
+        let mut signals = {
+            #[cfg(unix)]
+            let signals = Signals::new([Signal::Int, Signal::Term]);
+            #[cfg(not(unix))]
+            let signals = Signals::new([Signal::Int]);
+            signals.expect("failed to register signal handlers")
+        };
+        #[cfg(feature = "ctrlc-integration")]
+        let mut signals = ctrlc_signals();
+
+        async move {
+            futures_lite::pin!(future);
+            let output = future::or(async { Some(future.as_mut().await) }, async {
+                signals.next().await;
+                None
+            })
+            .await;
+            if output.is_some() {
+                return output;
+            }
+
+            crate::shutdown_channel().0.close();
+            if grace.is_zero() {
+                return None;
+            }
+            let deadline = async {
+                future::or(Timer::after(grace), async {
+                    signals.next().await;
+                    Instant::now()
+                })
+                .await;
+                None
+            };
+            future::or(async { Some(future.await) }, deadline).await
+        }
+    }
+
+    /// Returns the signals received by the `ctrlc` handler, installing it on first use.
+    ///
+    /// `ctrlc` only allows one handler per process, so every call shares it.
+    #[cfg(feature = "ctrlc-integration")]
+    fn ctrlc_signals() -> async_channel::Receiver<()> {
+        static SIGNALS: std::sync::OnceLock<async_channel::Receiver<()>> =
+            std::sync::OnceLock::new();
+        SIGNALS
+            .get_or_init(|| {
+                let (sender, receiver) = async_channel::unbounded();
+                ctrlc::set_handler(move || {
+                    let _ = sender.try_send(());
+                })
+                .expect("failed to register signal handlers");
+                receiver
+            })
+            .clone()
+    }
+
+    /// Reads the number of worker threads from `SMOL_THREADS`, if it is set. A value that isn't a
+    /// positive number is ignored with a warning.
+    pub fn env_threads() -> Option<usize> {
+        let value = std::env::var("SMOL_THREADS").ok()?;
+        let threads = value.parse().ok().filter(|threads| *threads > 0);
+        if threads.is_none() {
+            eprintln!(
+                "warning: SMOL_THREADS={} is ignored, it is not a positive number of threads",
+                value,
+            );
+        }
+        threads
+    }
+
+    /// Returns one worker thread per CPU, limited to the CPU quota of the cgroup the process
+    /// runs in, if it has one.
+    pub fn container_threads() -> usize {
+        let cpus = std::cmp::max(num_cpus::get(), 1);
+        match cgroup_quota() {
+            Some(quota) => cpus.min(quota),
+            None => cpus,
+        }
+    }
+
+    /// Reads the CPU quota from cgroup v2 `cpu.max`, or from the cgroup v1 CFS quota, rounded up
+    /// to whole CPUs.
+    #[cfg(target_os = "linux")]
+    fn cgroup_quota() -> Option<usize> {
+        use std::fs;
+
+        fn cpus(quota: &str, period: &str) -> Option<usize> {
+            let quota: u64 = quota.trim().parse().ok()?;
+            let period: u64 = period.trim().parse().ok().filter(|period| *period > 0)?;
+            Some(std::cmp::max(quota.div_ceil(period), 1) as usize)
+        }
+
+        let cgroup = fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+        let path = cgroup
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .unwrap_or("/");
+        for dir in [
+            format!("/sys/fs/cgroup{}", path.trim_end_matches('/')),
+            "/sys/fs/cgroup".into(),
+        ] {
+            if let Ok(max) = fs::read_to_string(format!("{}/cpu.max", dir)) {
+                // "max" means no quota.
+                let mut fields = max.split_whitespace();
+                return cpus(fields.next()?, fields.next().unwrap_or("100000"));
+            }
+        }
+
+        // A quota of -1 means none, which fails to parse.
+        let quota = fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_quota_us").ok()?;
+        let period = fs::read_to_string("/sys/fs/cgroup/cpu/cpu.cfs_period_us").ok()?;
+        cpus(&quota, &period)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn cgroup_quota() -> Option<usize> {
+        None
+    }
+
+    /// Reads the default test timeout in milliseconds from `SMOL_POTAT_TEST_TIMEOUT`, ignoring
+    /// values that are not a positive integer.
+    pub fn env_test_timeout() -> Option<u64> {
+        let timeout = std::env::var("SMOL_POTAT_TEST_TIMEOUT").ok()?;
+        timeout.trim().parse().ok().filter(|ms| *ms > 0)
+    }
+
+    /// Warns if `SMOL_THREADS` asks for a different number of worker threads than main runs.
+    pub fn warn_env_threads(threads: usize) -> usize {
+        if let Ok(value) = std::env::var("SMOL_THREADS") {
+            if value.parse() != Ok(threads) {
+                eprintln!(
+                    "warning: SMOL_THREADS={} is ignored, main runs with {} worker threads; \
+                     use `threads = \"respect_env\"` to read it",
+                    value, threads,
+                );
+            }
+        }
+        threads
+    }
+
+    /// Reads the number of worker threads from the `threads` field of a config file, parsed as
+    /// JSON if the path ends with `.json` and as TOML otherwise.
+    ///
+    /// Returns `None` if the file does not exist or has no `threads` field, and panics if it
+    /// cannot be read or parsed, or `threads` is not a positive integer.
+    #[cfg(feature = "config")]
+    pub fn config_threads(path: &str) -> Option<usize> {
+        use std::convert::TryFrom;
+
+        let config = match std::fs::read_to_string(path) {
+            Ok(config) => config,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(err) => panic!("failed to read threads_config file {}: {}", path, err),
+        };
+
+        let threads = if path.ends_with(".json") {
+            let config: serde_json::Value = serde_json::from_str(&config).unwrap_or_else(|err| {
+                panic!("failed to parse threads_config file {}: {}", path, err)
+            });
+            config.get("threads")?.as_u64()
+        } else {
+            let config: toml::Table = config.parse().unwrap_or_else(|err| {
+                panic!("failed to parse threads_config file {}: {}", path, err)
+            });
+            config
+                .get("threads")?
+                .as_integer()
+                .and_then(|threads| u64::try_from(threads).ok())
+        };
+
+        match threads.and_then(|threads| usize::try_from(threads).ok()) {
+            Some(threads) if threads > 0 => Some(threads),
+            _ => panic!(
+                "threads in threads_config file {} must be a positive integer",
+                path
+            ),
+        }
+    }
+
+    /// Limits the thread pool running blocking work like `smol::unblock`, taking precedence over
+    /// `BLOCKING_MAX_THREADS`.
+    pub fn set_blocking_threads(threads: usize) {
+        let threads = std::num::NonZeroUsize::new(threads).expect("blocking_threads must not be 0");
+        blocking::set_max_blocking_threads(threads);
+    }
+
+    /// Returns the executor that [`spawn`](crate::spawn) spawns onto, creating it on first use.
+    pub fn global_executor() -> Arc<Executor<'static>> {
+        crate::GLOBAL_EXECUTOR.get_or_init(Default::default).clone()
+    }
+
+    /// Captures what is logged while the future runs, see [`logs`](crate::logs).
+    #[cfg(feature = "log")]
+    pub fn capture_logs<F: Future>(future: F) -> impl Future<Output = F::Output> {
+        crate::logs::Capture::new(future)
+    }
+
+    /// Returns the logs captured for the test running on the current thread.
+    #[cfg(feature = "log")]
+    pub fn captured_logs() -> crate::logs::CapturedLogs {
+        crate::logs::current()
+    }
+
+    /// Prints the counts of the tasks spawned with [`spawn`](crate::spawn) so far.
+    pub fn print_task_metrics() {
+        crate::tasks::print_metrics();
+    }
+
+    /// Starts `threads` worker threads on the global executor for a benchmark, which stop when
+    /// the returned runtime is dropped.
+    pub fn bench_workers(threads: usize) -> crate::Runtime {
+        crate::Runtime::start(global_executor(), threads)
+    }
+
+    /// Runs the test's future, panicking if it and the tasks it spawned make no progress for
+    /// `window`.
+    pub fn detect_deadlock<F: Future>(
+        name: &'static str,
+        window: Duration,
+        future: F,
+    ) -> impl Future<Output = F::Output> {
+        crate::deadlock::detect(name, window, future)
+    }
+
+    /// Runs the test's future, resuming the first panic of the tasks it spawned with
+    /// [`spawn`](crate::spawn) once it completes. Unlike [`Builder::propagate_panics`], the panics
+    /// of tasks spawned by other tests on the same threads are left alone.
+    ///
+    /// [`Builder::propagate_panics`]: crate::Builder::propagate_panics
+    pub fn propagate_task_panics<F: Future>(future: F) -> impl Future<Output = F::Output> {
+        crate::tasks::Propagated::new(future)
+    }
+
+    /// Prints the pending tasks whenever the process receives `SIGQUIT`, see
+    /// [`dump_tasks`](crate::dump_tasks). Other platforms have no such signal, so this does
+    /// nothing there. Neither does it without the `async-signal` feature, or with
+    /// `ctrlc-integration`, which leaves the signal handlers to `ctrlc`.
+    #[cfg(feature = "diagnostics")]
+    pub fn dump_tasks_on_signal() {
+        #[cfg(all(unix, feature = "async-signal", not(feature = "ctrlc-integration")))]
+        {
+            use futures_lite::StreamExt;
+
+            let mut signals = async_signal::Signals::new([async_signal::Signal::Quit])
+                .expect("failed to register the SIGQUIT handler");
+            std::thread::Builder::new()
+                .name("smol-potat-diagnostics".to_string())
+                .spawn(move || {
+                    crate::block_on(async {
+                        while signals.next().await.is_some() {
+                            crate::diagnostics::print_tasks();
+                        }
+                    })
+                })
+                .expect("failed to spawn the diagnostics thread");
+        }
+    }
+
+    /// Waits for every task spawned with [`spawn`](crate::spawn) to finish.
+    pub async fn join_spawned() {
+        crate::tasks::join().await;
+    }
+
+    /// Coalesces the sleeps of [`time::sleep`](crate::time::sleep) to multiples of `interval`.
+    pub fn set_idle_poll(interval: Duration) {
+        crate::time::set_coalesce(interval);
+    }
+
+    /// Starts warning about polls that take longer than `threshold`, which most likely block.
+    pub fn detect_blocking(threshold: Duration) {
+        crate::watchdog::start(threshold);
+    }
+
+    /// Records the polls of main's future for [`detect_blocking`].
+    pub fn watch_blocking<F: Future>(future: F) -> impl Future<Output = F::Output> {
+        let mut future = Box::pin(future);
+        std::future::poll_fn(move |cx| crate::watchdog::poll(|| future.as_mut().poll(cx)))
+    }
+
+    /// Runs the test's future, panicking if it spawns more than `max` tasks with
+    /// [`spawn`](crate::spawn).
+    pub fn max_tasks<F: Future>(max: usize, future: F) -> impl Future<Output = F::Output> {
+        crate::tasks::Limited::new(max, future)
+    }
+
+    /// Runs the future with a paused clock, see [`time`](crate::time).
+    pub fn start_paused<F: Future>(future: F) -> impl Future<Output = F::Output> {
+        crate::time::Paused::new(future)
+    }
+
+    /// Runs `f` on a new thread named after the current one, resuming its panic if it panics.
+    pub fn isolated<T, F>(f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let mut builder = std::thread::Builder::new();
+        if let Some(name) = std::thread::current().name() {
+            builder = builder.name(name.to_string());
+        }
+        let thread = builder.spawn(f).expect("failed to spawn the test thread");
+        match thread.join() {
+            Ok(output) => output,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// Runs a test's teardown when dropped, see [`teardown`].
+    pub struct Teardown<F: FnOnce()>(Option<F>);
+
+    impl<F: FnOnce()> Drop for Teardown<F> {
+        fn drop(&mut self) {
+            if let Some(teardown) = self.0.take() {
+                teardown();
+            }
+        }
+    }
+
+    /// Returns a guard that blocks on the future returned by `teardown` when dropped, so the
+    /// teardown also runs if the test panics or is cancelled.
+    pub fn teardown<F, Fut>(teardown: F) -> Teardown<impl FnOnce()>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future,
+    {
+        Teardown(Some(move || {
+            crate::block_on(teardown());
+        }))
+    }
+
+    /// How often the body of a `fair` main yields, in polls.
+    const FAIR_POLLS: u32 = 32;
+
+    /// Yields to the executor every [`FAIR_POLLS`] polls of the future, instead of polling it.
+    pub fn fair<F: Future>(future: F) -> impl Future<Output = F::Output> {
+        let mut future = Box::pin(future);
+        let mut polls: u32 = 0;
+        std::future::poll_fn(move |cx| {
+            polls = polls.wrapping_add(1);
+            if polls.is_multiple_of(FAIR_POLLS) {
+                cx.waker().wake_by_ref();
+                return std::task::Poll::Pending;
+            }
+            future.as_mut().poll(cx)
+        })
+    }
+
+    /// Reached through a custom `crate` root by the generated code, which it expands to.
+    pub use crate::__smol_potat_with_crate_root as with_crate_root;
+
+    /// Formats an error with `Display`, followed by the chain of its sources.
+    pub fn format_error(err: impl Into<Box<dyn Error>>) -> String {
+        let err = err.into();
+        let mut message = err.to_string();
+        let mut source = err.source();
+        if source.is_some() {
+            message.push_str("\n\nCaused by:");
+        }
+        while let Some(err) = source {
+            message.push_str("\n    ");
+            message.push_str(&err.to_string());
+            source = err.source();
+        }
+        message
+    }
+
+    /// Prints the seed of a test with `seed` if the test panics, so the failure can be reproduced.
+    pub struct SeedGuard(pub u64);
+
+    impl Drop for SeedGuard {
+        fn drop(&mut self) {
+            if std::thread::panicking() {
+                eprintln!("the test failed with seed = {}", self.0);
+            }
+        }
+    }
+
+    /// Fails a test with an `anyhow` error, printing the error with its context and backtrace
+    /// first.
+    #[cfg(feature = "anyhow")]
+    pub fn fail_anyhow(err: impl Into<anyhow::Error>) -> ! {
+        let err = err.into();
+        eprintln!("Error: {:?}", err);
+        panic!("{:#}", err);
+    }
+
+    /// The value returned from main when it was cancelled by a signal.
+    pub trait Interrupted {
+        fn interrupted() -> Self;
+    }
+
+    impl Interrupted for () {
+        fn interrupted() -> Self {}
+    }
+
+    impl Interrupted for ExitCode {
+        fn interrupted() -> Self {
+            ExitCode::SUCCESS
+        }
+    }
+
+    impl<T: Interrupted, E> Interrupted for Result<T, E> {
+        fn interrupted() -> Self {
+            Ok(T::interrupted())
+        }
+    }
+
+    /// The outcome of a single run of a test, used to decide whether it should be retried.
+    pub trait TestOutcome {
+        fn is_failure(&self) -> bool;
+    }
+
+    impl TestOutcome for () {
+        fn is_failure(&self) -> bool {
+            false
+        }
+    }
+
+    impl TestOutcome for ExitCode {
+        fn is_failure(&self) -> bool {
+            *self != ExitCode::SUCCESS
+        }
+    }
+
+    impl<T, E> TestOutcome for Result<T, E> {
+        fn is_failure(&self) -> bool {
+            self.is_err()
+        }
+    }
+
+    /// The outcome of a single case of a proptest, as reported to its runner.
+    #[cfg(feature = "proptest")]
+    pub trait ProptestOutcome {
+        fn into_case_result(self) -> Result<(), proptest::test_runner::TestCaseError>;
+    }
+
+    #[cfg(feature = "proptest")]
+    impl ProptestOutcome for () {
+        fn into_case_result(self) -> Result<(), proptest::test_runner::TestCaseError> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "proptest")]
+    impl ProptestOutcome for ExitCode {
+        fn into_case_result(self) -> Result<(), proptest::test_runner::TestCaseError> {
+            if self == ExitCode::SUCCESS {
+                Ok(())
+            } else {
+                Err(proptest::test_runner::TestCaseError::fail(format!(
+                    "the test returned {:?}",
+                    self
+                )))
+            }
+        }
+    }
+
+    #[cfg(feature = "proptest")]
+    impl<T, E: std::fmt::Debug> ProptestOutcome for Result<T, E> {
+        fn into_case_result(self) -> Result<(), proptest::test_runner::TestCaseError> {
+            self.map(drop).map_err(|err| {
+                proptest::test_runner::TestCaseError::fail(format!("Error: {:?}", err))
+            })
+        }
+    }
+}
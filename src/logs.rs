@@ -0,0 +1,120 @@
+//! Log lines captured by a test.
+//!
+//! Tests marked with `#[smol_potat::test(capture_logs)]` record the lines logged through the
+//! [`log`] facade while they run, and get them as `logs`, a [`CapturedLogs`]:
+//!
+//! ```ignore
+//! #[smol_potat::test(capture_logs)]
+//! async fn my_test() {
+//!     log::info!("connected to {}", "localhost");
+//!     assert!(logs.contains("connected to localhost"));
+//! }
+//! ```
+//!
+//! [`log`]: https://docs.rs/log
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Once};
+use std::task::{Context, Poll};
+
+use log::{Log, Metadata, Record};
+
+thread_local! {
+    static CURRENT: RefCell<Option<CapturedLogs>> = const { RefCell::new(None) };
+}
+
+/// The lines logged by a test, each formatted as its level followed by its message, like
+/// `INFO connected to localhost`.
+#[derive(Clone, Debug, Default)]
+pub struct CapturedLogs {
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl CapturedLogs {
+    /// Returns the lines logged so far.
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().clone()
+    }
+
+    /// Returns `true` if one of the lines logged so far contains `text`.
+    pub fn contains(&self, text: &str) -> bool {
+        self.lines
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|line| line.contains(text))
+    }
+}
+
+/// Forwards every record to the captured logs of the test being polled on the current thread.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        CURRENT.with(|current| current.borrow().is_some())
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        CURRENT.with(|current| {
+            if let Some(logs) = &*current.borrow() {
+                let line = format!("{} {}", record.level(), record.args());
+                logs.lines.lock().unwrap().push(line);
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the capturing logger, panicking if another logger was installed first.
+fn install() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        log::set_logger(&Logger).expect("capture_logs needs to be the only logger of the process");
+        log::set_max_level(log::LevelFilter::Trace);
+    });
+}
+
+/// Returns the logs captured for the test being polled on the current thread.
+pub(crate) fn current() -> CapturedLogs {
+    CURRENT
+        .with(|current| current.borrow().clone())
+        .expect("captured logs are only available in a test with capture_logs")
+}
+
+/// Captures what is logged while the future is polled.
+pub(crate) struct Capture<F> {
+    future: Pin<Box<F>>,
+    logs: CapturedLogs,
+}
+
+impl<F: Future> Capture<F> {
+    pub(crate) fn new(future: F) -> Capture<F> {
+        install();
+        Capture {
+            future: Box::pin(future),
+            logs: CapturedLogs::default(),
+        }
+    }
+}
+
+impl<F: Future> Future for Capture<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        /// Restores the logs that were current before, even if the future panics.
+        struct Restore(Option<CapturedLogs>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CURRENT.with(|current| current.replace(self.0.take()));
+            }
+        }
+
+        let this = &mut *self;
+        let _restore = Restore(CURRENT.with(|current| current.replace(Some(this.logs.clone()))));
+        this.future.as_mut().poll(cx)
+    }
+}
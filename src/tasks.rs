@@ -0,0 +1,310 @@
+//! Counters for the tasks spawned with [`spawn`](crate::spawn).
+//!
+//! Every such task is wrapped in a [`Tracked`] future, which keeps these up to date. Tasks
+//! spawned directly onto an executor aren't counted.
+//!
+//! A test with `max_tasks` runs in a [`Limited`] future, whose budget is inherited by the tasks
+//! spawned while it or one of those tasks is polled, on whichever thread they run. A threaded
+//! test with `#[should_panic]` runs in a [`Propagated`] future, whose panic slot is inherited the
+//! same way, so only the panics of its own tasks are resumed. A test with `deadlock_detect` runs
+//! in a [`Watched`] future, whose [`Progress`] counts the polls of its own tasks likewise.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::LocalKey;
+
+#[cfg(feature = "diagnostics")]
+use crate::diagnostics::Registered;
+use crate::{panic_message, watchdog, FirstPanic};
+
+/// The number of tasks spawned with `spawn`.
+static SPAWNED: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of tasks spawned with `spawn` that ran to completion.
+static COMPLETED: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of tasks spawned with `spawn` that haven't completed or been cancelled.
+static PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// The largest number of tasks that were pending at once.
+static MAX_PENDING: AtomicUsize = AtomicUsize::new(0);
+
+/// The futures waiting for every pending task to finish.
+static JOINERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static BUDGET: RefCell<Option<Arc<Budget>>> = const { RefCell::new(None) };
+    static PANICS: RefCell<Option<FirstPanic>> = const { RefCell::new(None) };
+    static PROGRESS: RefCell<Option<Arc<Progress>>> = const { RefCell::new(None) };
+}
+
+/// The number of tasks a test with `max_tasks` may spawn.
+struct Budget {
+    max: usize,
+    spawned: AtomicUsize,
+}
+
+/// The polls and pending tasks of a test with `deadlock_detect`, counting only its own tasks.
+#[derive(Default)]
+pub(crate) struct Progress {
+    polls: AtomicUsize,
+    pending: AtomicUsize,
+}
+
+impl Progress {
+    /// Returns the number of times the test's tasks have been polled.
+    pub(crate) fn polls(&self) -> usize {
+        self.polls.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of the test's tasks that are pending.
+    pub(crate) fn pending(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+}
+
+/// Makes `value` current in `key` while a future is polled, restoring the previous value
+/// afterwards, even if the future panics.
+fn with_current<T: Clone + 'static, R>(
+    key: &'static LocalKey<RefCell<Option<T>>>,
+    value: &Option<T>,
+    poll: impl FnOnce() -> R,
+) -> R {
+    struct Restore<T: 'static>(&'static LocalKey<RefCell<Option<T>>>, Option<T>);
+
+    impl<T> Drop for Restore<T> {
+        fn drop(&mut self) {
+            let previous = self.1.take();
+            self.0.with(|current| current.replace(previous));
+        }
+    }
+
+    if value.is_none() {
+        return poll();
+    }
+    let _restore = Restore(key, key.with(|current| current.replace(value.clone())));
+    poll()
+}
+
+/// A task spawned with `spawn`, counted while it is pending.
+pub(crate) struct Tracked<F> {
+    future: Pin<Box<F>>,
+    budget: Option<Arc<Budget>>,
+    panics: Option<FirstPanic>,
+    progress: Option<Arc<Progress>>,
+    #[cfg(feature = "diagnostics")]
+    registered: Registered,
+}
+
+impl<F: Future> Tracked<F> {
+    /// Panics if the task goes over the budget of the test spawning it.
+    pub(crate) fn new(name: Option<String>, future: F) -> Tracked<F> {
+        let budget = BUDGET.with(|current| current.borrow().clone());
+        if let Some(budget) = &budget {
+            let spawned = budget.spawned.fetch_add(1, Ordering::Relaxed) + 1;
+            if spawned > budget.max {
+                panic!(
+                    "the test spawned more than {} tasks, the limit set by max_tasks",
+                    budget.max,
+                );
+            }
+        }
+
+        #[cfg(not(feature = "diagnostics"))]
+        let _ = name;
+        SPAWNED.fetch_add(1, Ordering::Relaxed);
+        let pending = PENDING.fetch_add(1, Ordering::Relaxed) + 1;
+        MAX_PENDING.fetch_max(pending, Ordering::Relaxed);
+        let progress = PROGRESS.with(|current| current.borrow().clone());
+        if let Some(progress) = &progress {
+            progress.pending.fetch_add(1, Ordering::Relaxed);
+        }
+        Tracked {
+            future: Box::pin(future),
+            budget,
+            panics: PANICS.with(|current| current.borrow().clone()),
+            progress,
+            #[cfg(feature = "diagnostics")]
+            registered: Registered::new(name.unwrap_or_else(|| "<unnamed>".to_string())),
+        }
+    }
+}
+
+impl<F: Future> Future for Tracked<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = &mut *self;
+        if let Some(progress) = &this.progress {
+            progress.polls.fetch_add(1, Ordering::Relaxed);
+        }
+        let future = &mut this.future;
+        let poll = || watchdog::poll(|| future.as_mut().poll(cx));
+        #[cfg(feature = "diagnostics")]
+        let registered = &this.registered;
+        #[cfg(feature = "diagnostics")]
+        let poll = || registered.poll(poll);
+        let budget = &this.budget;
+        let poll = || with_current(&BUDGET, budget, poll);
+        let progress = &this.progress;
+        let poll = || with_current(&PROGRESS, progress, poll);
+        let panics = &this.panics;
+        let poll = match panics {
+            // The executor catches the panic, so it is recorded on the way out.
+            Some(slot) => {
+                match panic::catch_unwind(AssertUnwindSafe(|| with_current(&PANICS, panics, poll)))
+                {
+                    Ok(poll) => poll,
+                    Err(payload) => {
+                        slot.lock()
+                            .unwrap()
+                            .get_or_insert_with(|| panic_message(&*payload));
+                        panic::resume_unwind(payload);
+                    }
+                }
+            }
+            None => poll(),
+        };
+        if poll.is_ready() {
+            COMPLETED.fetch_add(1, Ordering::Relaxed);
+        }
+        poll
+    }
+}
+
+impl<F> Drop for Tracked<F> {
+    fn drop(&mut self) {
+        if let Some(progress) = &self.progress {
+            progress.pending.fetch_sub(1, Ordering::Relaxed);
+        }
+        if PENDING.fetch_sub(1, Ordering::SeqCst) == 1 {
+            for waker in JOINERS.lock().unwrap().drain(..) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Runs a test's future with a budget of `max` spawned tasks.
+pub(crate) struct Limited<F> {
+    future: Pin<Box<F>>,
+    budget: Option<Arc<Budget>>,
+}
+
+impl<F: Future> Limited<F> {
+    pub(crate) fn new(max: usize, future: F) -> Limited<F> {
+        Limited {
+            future: Box::pin(future),
+            budget: Some(Arc::new(Budget {
+                max,
+                spawned: AtomicUsize::new(0),
+            })),
+        }
+    }
+}
+
+impl<F: Future> Future for Limited<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = &mut *self;
+        let future = &mut this.future;
+        with_current(&BUDGET, &this.budget, || future.as_mut().poll(cx))
+    }
+}
+
+/// Runs a test's future, resuming the first panic of the tasks it spawned once it completes.
+pub(crate) struct Propagated<F> {
+    future: Pin<Box<F>>,
+    panics: Option<FirstPanic>,
+}
+
+impl<F: Future> Propagated<F> {
+    pub(crate) fn new(future: F) -> Propagated<F> {
+        Propagated {
+            future: Box::pin(future),
+            panics: Some(Default::default()),
+        }
+    }
+}
+
+impl<F: Future> Future for Propagated<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = &mut *self;
+        let future = &mut this.future;
+        let poll = with_current(&PANICS, &this.panics, || future.as_mut().poll(cx));
+        if poll.is_ready() {
+            let panics = this
+                .panics
+                .as_ref()
+                .and_then(|slot| slot.lock().unwrap().take());
+            if let Some(message) = panics {
+                panic::resume_unwind(Box::new(message));
+            }
+        }
+        poll
+    }
+}
+
+/// Runs a test's future, counting the progress of the tasks it spawns in `progress`.
+pub(crate) struct Watched<F> {
+    future: Pin<Box<F>>,
+    progress: Option<Arc<Progress>>,
+}
+
+impl<F: Future> Watched<F> {
+    pub(crate) fn new(progress: Arc<Progress>, future: F) -> Watched<F> {
+        Watched {
+            future: Box::pin(future),
+            progress: Some(progress),
+        }
+    }
+}
+
+impl<F: Future> Future for Watched<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let this = &mut *self;
+        let future = &mut this.future;
+        with_current(&PROGRESS, &this.progress, || future.as_mut().poll(cx))
+    }
+}
+
+/// Returns the number of tasks that are pending.
+pub(crate) fn pending() -> usize {
+    PENDING.load(Ordering::Relaxed)
+}
+
+/// Waits until no task is pending, including the tasks spawned while waiting.
+pub(crate) async fn join() {
+    std::future::poll_fn(|cx| {
+        if pending() == 0 {
+            return Poll::Ready(());
+        }
+        JOINERS.lock().unwrap().push(cx.waker().clone());
+        // The last task may have finished before the waker was registered.
+        match pending() {
+            0 => Poll::Ready(()),
+            _ => Poll::Pending,
+        }
+    })
+    .await
+}
+
+/// Prints how many tasks were spawned and completed, and how many were pending at once.
+pub(crate) fn print_metrics() {
+    eprintln!(
+        "task metrics: {} spawned, {} completed, at most {} at once",
+        SPAWNED.load(Ordering::Relaxed),
+        COMPLETED.load(Ordering::Relaxed),
+        MAX_PENDING.load(Ordering::Relaxed),
+    );
+}
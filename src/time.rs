@@ -0,0 +1,293 @@
+//! A clock that tests can pause.
+//!
+//! `async-io` timers always wait in real time. Tests marked with
+//! `#[smol_potat::test(start_paused)]` run with a paused clock instead: [`sleep`] and [`now`] use
+//! virtual time, which jumps straight to the next deadline whenever the test has nothing else to
+//! do. Outside of such a test they behave like [`async_io::Timer`] and [`Instant::now`].
+//!
+//! The test is taken to have nothing else to do when the sleeps hold every clone of its waker
+//! that it kept, as anything else it waits on, like I/O or a channel, keeps one too. So the clock
+//! doesn't move while the test waits on other work, even work raced against a sleep: a timeout
+//! around a channel only fires once something wakes the test. A future that keeps the waker
+//! without waiting on anything holds the clock still as well.
+//!
+//! A main function with `idle_poll_ms` coalesces the real sleeps instead: their deadlines are
+//! rounded up to the next multiple of the interval, so sleeps ending close together wake the
+//! program once.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! smol_potat::block_on(async {
+//!     let start = smol_potat::time::now();
+//!     smol_potat::time::sleep(Duration::from_millis(10)).await;
+//!     assert!(smol_potat::time::now() - start >= Duration::from_millis(10));
+//! });
+//! ```
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use async_io::Timer;
+
+/// The interval real sleeps are coalesced to, in microseconds, or 0 if they aren't.
+static COALESCE: AtomicU64 = AtomicU64::new(0);
+
+/// The time coalesced deadlines are multiples of the interval from.
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+thread_local! {
+    static CLOCK: RefCell<Option<Rc<RefCell<Clock>>>> = const { RefCell::new(None) };
+}
+
+/// The virtual time of a paused test and the sleeps waiting on it.
+struct Clock {
+    now: Instant,
+    /// The deadline and waker of each pending sleep, by the key the sleep was given.
+    sleepers: BTreeMap<u64, (Instant, Waker)>,
+    next_key: u64,
+}
+
+impl Clock {
+    /// Moves the clock to the earliest deadline and removes the sleeps that are due, returning
+    /// their wakers, or `None` if nothing was sleeping.
+    fn advance(&mut self) -> Option<Vec<Waker>> {
+        let next = self
+            .sleepers
+            .values()
+            .map(|(deadline, _)| *deadline)
+            .min()?;
+        self.now = self.now.max(next);
+
+        let now = self.now;
+        let due: Vec<u64> = self
+            .sleepers
+            .iter()
+            .filter(|(_, (deadline, _))| *deadline <= now)
+            .map(|(key, _)| *key)
+            .collect();
+        let wakers = due
+            .into_iter()
+            .filter_map(|key| self.sleepers.remove(&key))
+            .map(|(_, waker)| waker)
+            .collect();
+        Some(wakers)
+    }
+
+    /// Returns the number of sleeps that would wake `waker`.
+    fn holding(&self, waker: &Waker) -> usize {
+        self.sleepers
+            .values()
+            .filter(|(_, sleeper)| sleeper.will_wake(waker))
+            .count()
+    }
+}
+
+fn current() -> Option<Rc<RefCell<Clock>>> {
+    CLOCK.with(|clock| clock.borrow().clone())
+}
+
+/// Returns the current time, which is virtual inside a paused test.
+pub fn now() -> Instant {
+    match current() {
+        Some(clock) => clock.borrow().now,
+        None => Instant::now(),
+    }
+}
+
+/// Waits until `duration` has elapsed.
+///
+/// Inside a paused test, this completes as soon as the test is otherwise idle and virtual time
+/// reaches the deadline.
+pub fn sleep(duration: Duration) -> Sleep {
+    let inner = match current() {
+        Some(clock) => {
+            let deadline = clock.borrow().now + duration;
+            Inner::Paused {
+                clock,
+                deadline,
+                key: None,
+            }
+        }
+        None => Inner::Real(Timer::at(coalesce(Instant::now() + duration))),
+    };
+    Sleep { inner }
+}
+
+/// Rounds real sleeps up to multiples of `interval`, see `idle_poll_ms`.
+pub(crate) fn set_coalesce(interval: Duration) {
+    EPOCH.get_or_init(Instant::now);
+    COALESCE.store(interval.as_micros() as u64, Ordering::Relaxed);
+}
+
+/// Rounds the deadline up to the next multiple of the coalescing interval, if there is one.
+fn coalesce(deadline: Instant) -> Instant {
+    let interval = COALESCE.load(Ordering::Relaxed);
+    let epoch = match EPOCH.get() {
+        Some(epoch) if interval > 0 => *epoch,
+        _ => return deadline,
+    };
+    let since = deadline.saturating_duration_since(epoch).as_micros() as u64;
+    epoch + Duration::from_micros(since.div_ceil(interval) * interval)
+}
+
+/// The future returned by [`sleep`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Sleep {
+    inner: Inner,
+}
+
+enum Inner {
+    Real(Timer),
+    Paused {
+        clock: Rc<RefCell<Clock>>,
+        deadline: Instant,
+        /// The key of the sleep in the clock, once it has been registered.
+        key: Option<u64>,
+    },
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.inner {
+            Inner::Real(timer) => Pin::new(timer).poll(cx).map(|_| ()),
+            Inner::Paused {
+                clock,
+                deadline,
+                key,
+            } => {
+                let mut clock = clock.borrow_mut();
+                if clock.now >= *deadline {
+                    if let Some(key) = key.take() {
+                        clock.sleepers.remove(&key);
+                    }
+                    return Poll::Ready(());
+                }
+                // Polled again, the sleep replaces its waker rather than adding another one.
+                let key = *key.get_or_insert_with(|| {
+                    clock.next_key += 1;
+                    clock.next_key
+                });
+                clock.sleepers.insert(key, (*deadline, cx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Inner::Paused {
+            clock,
+            key: Some(key),
+            ..
+        } = &self.inner
+        {
+            clock.borrow_mut().sleepers.remove(key);
+        }
+    }
+}
+
+impl std::fmt::Debug for Sleep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sleep")
+            .field("paused", &matches!(self.inner, Inner::Paused { .. }))
+            .finish()
+    }
+}
+
+/// Runs a future with a paused clock, advancing it whenever the future is idle.
+pub(crate) struct Paused<F> {
+    future: Pin<Box<F>>,
+    clock: Rc<RefCell<Clock>>,
+}
+
+impl<F: Future> Paused<F> {
+    pub(crate) fn new(future: F) -> Paused<F> {
+        Paused {
+            future: Box::pin(future),
+            clock: Rc::new(RefCell::new(Clock {
+                now: Instant::now(),
+                sleepers: BTreeMap::new(),
+                next_key: 0,
+            })),
+        }
+    }
+}
+
+/// Forwards wake-ups and records that one happened.
+struct Flag {
+    woken: AtomicBool,
+    waker: Mutex<Waker>,
+}
+
+impl Wake for Flag {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::SeqCst);
+        self.waker.lock().unwrap().wake_by_ref();
+    }
+}
+
+impl<F: Future> Future for Paused<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        /// Restores the clock that was current before, even if the future panics.
+        struct Restore(Option<Rc<RefCell<Clock>>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                CLOCK.with(|clock| clock.replace(self.0.take()));
+            }
+        }
+
+        let this = &mut *self;
+        let _restore = Restore(CLOCK.with(|clock| clock.replace(Some(this.clock.clone()))));
+
+        loop {
+            let flag = Arc::new(Flag {
+                woken: AtomicBool::new(false),
+                waker: Mutex::new(cx.waker().clone()),
+            });
+            let waker = Waker::from(flag.clone());
+            if let Poll::Ready(output) = this.future.as_mut().poll(&mut Context::from_waker(&waker))
+            {
+                return Poll::Ready(output);
+            }
+
+            // Only jump ahead when the future is waiting on nothing but the clock: it wasn't
+            // woken while it was polled, and the sleeps hold every clone of its waker besides the
+            // two here.
+            let mut clock = this.clock.borrow_mut();
+            if flag.woken.load(Ordering::SeqCst)
+                || Arc::strong_count(&flag) - 2 > clock.holding(&waker)
+            {
+                return Poll::Pending;
+            }
+            let wakers = match clock.advance() {
+                Some(wakers) => wakers,
+                None => return Poll::Pending,
+            };
+            // Woken once the clock is released, as waking may poll a sleep.
+            drop(clock);
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Detection of blocking calls for main with `detect_blocking`.
+//!
+//! Async code is expected to return `Pending` instead of waiting, so a single poll taking long is
+//! most likely a blocking call stalling the thread running it. Every thread records when the poll
+//! it is running started, for main's own future and the tasks spawned with
+//! [`spawn`](crate::spawn), and a watchdog thread reports the polls running longer than the
+//! threshold.
+
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Whether polls are recorded, which is only the case once the watchdog runs.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The threads that have run a poll since the watchdog started.
+static THREADS: Mutex<Vec<Arc<Slot>>> = Mutex::new(Vec::new());
+
+/// The time polls are measured from.
+static START: OnceLock<Instant> = OnceLock::new();
+
+thread_local! {
+    static SLOT: RefCell<Option<Arc<Slot>>> = const { RefCell::new(None) };
+}
+
+/// The poll a thread is running.
+struct Slot {
+    name: String,
+    /// When the poll started, in microseconds since `START` plus one, or 0 between polls.
+    started: AtomicU64,
+    /// Whether the current poll has been reported already.
+    reported: AtomicBool,
+}
+
+fn now() -> u64 {
+    let start = START.get_or_init(Instant::now);
+    start.elapsed().as_micros() as u64 + 1
+}
+
+fn slot() -> Arc<Slot> {
+    SLOT.with(|slot| {
+        slot.borrow_mut()
+            .get_or_insert_with(|| {
+                let thread = thread::current();
+                let slot = Arc::new(Slot {
+                    name: thread.name().unwrap_or("<unnamed>").to_string(),
+                    started: AtomicU64::new(0),
+                    reported: AtomicBool::new(false),
+                });
+                THREADS.lock().unwrap().push(slot.clone());
+                slot
+            })
+            .clone()
+    })
+}
+
+/// Runs a poll, recording when it started while the watchdog runs.
+pub(crate) fn poll<T>(poll: impl FnOnce() -> T) -> T {
+    /// Marks the thread as idle again, even if the poll panics.
+    struct Idle(Arc<Slot>);
+
+    impl Drop for Idle {
+        fn drop(&mut self) {
+            self.0.started.store(0, Ordering::Relaxed);
+            self.0.reported.store(false, Ordering::Relaxed);
+        }
+    }
+
+    if !ENABLED.load(Ordering::Relaxed) {
+        return poll();
+    }
+
+    let slot = slot();
+    // A poll blocking on another future keeps the start of the outer one.
+    if slot.started.load(Ordering::Relaxed) != 0 {
+        return poll();
+    }
+    slot.started.store(now(), Ordering::Relaxed);
+    let _idle = Idle(slot);
+    poll()
+}
+
+/// Starts the watchdog, which warns about every poll running longer than `threshold`.
+pub(crate) fn start(threshold: Duration) {
+    if ENABLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let check = (threshold / 4).max(Duration::from_millis(1));
+    let threshold = threshold.as_micros() as u64;
+    thread::Builder::new()
+        .name("smol-potat-watchdog".to_string())
+        .spawn(move || loop {
+            thread::sleep(check);
+            let now = now();
+            for slot in THREADS.lock().unwrap().iter() {
+                let started = slot.started.load(Ordering::Relaxed);
+                if started == 0 || now.saturating_sub(started) < threshold {
+                    continue;
+                }
+                if slot.reported.swap(true, Ordering::Relaxed) {
+                    continue;
+                }
+                eprintln!(
+                    "warning: thread '{}' has been polling a task for {}ms without yielding; \
+                     a blocking call stalls the executor, run it with `smol::unblock` instead",
+                    slot.name,
+                    (now - started) / 1000,
+                );
+            }
+        })
+        .expect("failed to spawn the watchdog thread");
+}
@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+#[smol_potat::main(allow_sync, threads = 2)]
+fn main() -> std::io::Result<()> {
+    let task = smol_potat::spawn(async { 1 + 2 });
+    assert_eq!(smol_potat::block_on(task), 3);
+
+    smol_potat::block_on(async {
+        smol::Timer::after(Duration::from_millis(1)).await;
+    });
+    Ok(())
+}
@@ -0,0 +1,21 @@
+use anyhow::Context;
+
+fn read_config() -> std::io::Result<String> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        "no such file",
+    ))
+}
+
+#[smol_potat::test(anyhow)]
+async fn test_anyhow() -> anyhow::Result<()> {
+    smol::future::yield_now().await;
+    Ok(())
+}
+
+#[smol_potat::test(anyhow)]
+#[should_panic(expected = "failed to read config.toml: no such file")]
+async fn test_anyhow_context() -> anyhow::Result<()> {
+    read_config().context("failed to read config.toml")?;
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static WORKERS_STARTED: AtomicUsize = AtomicUsize::new(0);
+
+fn count_worker() {
+    WORKERS_STARTED.fetch_add(1, Ordering::SeqCst);
+}
+
+// Explicit threads win over the number of CPUs the `auto` feature would use.
+#[smol_potat::main(threads = 3, on_thread_start = count_worker)]
+async fn main() {}
+
+#[test]
+fn test_threads_override_auto() {
+    main();
+    // The workers have been joined once main returns.
+    assert_eq!(WORKERS_STARTED.load(Ordering::SeqCst), 3);
+}
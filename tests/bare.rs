@@ -0,0 +1,23 @@
+use std::env;
+use std::error::Error;
+use std::process::Command;
+use std::thread;
+
+#[smol_potat::main(bare)]
+async fn main() -> Result<(), Box<dyn Error>> {
+    assert_eq!(thread::current().name(), Some("main"));
+    smol::Timer::after(std::time::Duration::from_millis(1)).await;
+
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        return Ok(());
+    }
+
+    // A bare main doesn't look at the environment, so it has nothing to warn about.
+    let output = Command::new(env::current_exe()?)
+        .env("SMOL_POTAT_CHILD", "1")
+        .env("SMOL_THREADS", "3")
+        .output()?;
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    Ok(())
+}
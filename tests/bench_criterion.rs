@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use criterion::Criterion;
+
+#[smol_potat::bench]
+async fn bench_lifetimes<'a>()
+where
+    u64: Default,
+{
+    let name: &'a str = "bench";
+    std::hint::black_box((name, u64::default()));
+}
+
+#[smol_potat::bench]
+async fn bench_generic<'a, T = u64>()
+where
+    T: Default + Copy,
+    'a: 'a,
+{
+    let name: &'a str = "bench";
+    std::hint::black_box((name, T::default()));
+}
+
+#[test]
+fn criterion_generics() {
+    let mut criterion = Criterion::default()
+        .sample_size(10)
+        .warm_up_time(Duration::from_millis(10))
+        .measurement_time(Duration::from_millis(10))
+        .nresamples(1000)
+        .without_plots();
+    bench_lifetimes(&mut criterion);
+    bench_generic(&mut criterion);
+}
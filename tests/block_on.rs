@@ -0,0 +1,21 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CALLED: AtomicBool = AtomicBool::new(false);
+
+fn record<F: Future>(future: F) -> F::Output {
+    CALLED.store(true, Ordering::SeqCst);
+    smol::block_on(future)
+}
+
+#[smol_potat::main(block_on = record)]
+async fn main() -> u8 {
+    smol::future::yield_now().await;
+    42
+}
+
+#[test]
+fn test_block_on() {
+    assert_eq!(main(), 42);
+    assert!(CALLED.load(Ordering::SeqCst));
+}
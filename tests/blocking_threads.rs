@@ -0,0 +1,14 @@
+#[smol_potat::main(blocking_threads = 2)]
+async fn main() -> u32 {
+    let tasks: Vec<_> = (0..4u32).map(|i| smol::unblock(move || i * 2)).collect();
+    let mut sum = 0;
+    for task in tasks {
+        sum += task.await;
+    }
+    sum
+}
+
+#[test]
+fn test_blocking_threads() {
+    assert_eq!(main(), 12);
+}
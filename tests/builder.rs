@@ -0,0 +1,187 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+use smol_potat::{Builder, Executor};
+
+#[test]
+fn spawned_tasks_run_on_worker_threads() {
+    let ex = Arc::new(Executor::new());
+    let main_thread = thread::current().id();
+
+    let worker_threads = Builder::new()
+        .threads(2)
+        .executor(ex.clone())
+        .build_block_on(async move {
+            // The main future blocks its own thread in the barrier, so both tasks can only get
+            // through it by running on the two workers at the same time.
+            let barrier = Arc::new(Barrier::new(3));
+            let tasks: Vec<_> = (0..2)
+                .map(|_| {
+                    let barrier = barrier.clone();
+                    ex.spawn(async move {
+                        barrier.wait();
+                        thread::current().id()
+                    })
+                })
+                .collect();
+            barrier.wait();
+
+            let mut ids = Vec::new();
+            for task in tasks {
+                ids.push(task.await);
+            }
+            ids
+        });
+
+    assert!(worker_threads.iter().all(|id| *id != main_thread));
+    assert_ne!(worker_threads[0], worker_threads[1]);
+}
+
+#[test]
+fn worker_threads_are_named() {
+    let ex = Arc::new(Executor::new());
+
+    let name = Builder::new()
+        .threads(1)
+        .thread_name("worker")
+        .executor(ex.clone())
+        .build_block_on(async move {
+            let barrier = Arc::new(Barrier::new(2));
+            let task = ex.spawn({
+                let barrier = barrier.clone();
+                async move {
+                    barrier.wait();
+                    thread::current().name().map(String::from)
+                }
+            });
+            // Blocking here keeps the task off the main thread.
+            barrier.wait();
+            task.await
+        });
+
+    assert_eq!(name.as_deref(), Some("worker-0"));
+}
+
+#[test]
+fn worker_threads_have_stack_size() {
+    // Overflows the default 2 MiB stack of spawned threads.
+    fn use_stack() -> u8 {
+        let buf = std::hint::black_box([1u8; 3 << 20]);
+        buf[buf.len() - 1]
+    }
+
+    let ex = Arc::new(Executor::new());
+
+    let output = Builder::new()
+        .threads(1)
+        .stack_size(32 << 20)
+        .executor(ex.clone())
+        .build_block_on(async move {
+            let barrier = Arc::new(Barrier::new(2));
+            let task = ex.spawn({
+                let barrier = barrier.clone();
+                async move {
+                    barrier.wait();
+                    use_stack()
+                }
+            });
+            barrier.wait();
+            task.await
+        });
+
+    assert_eq!(output, 1);
+}
+
+#[test]
+fn workers_survive_panicking_tasks() {
+    let ex = Arc::new(Executor::new());
+
+    let name = Builder::new()
+        .threads(1)
+        .thread_name("worker")
+        .executor(ex.clone())
+        .build_block_on(async move {
+            // Each barrier keeps the main thread blocked, so the task runs on the only worker.
+            let barrier = Arc::new(Barrier::new(2));
+            ex.spawn({
+                let barrier = barrier.clone();
+                async move {
+                    barrier.wait();
+                    panic!("task panicked");
+                }
+            })
+            .detach();
+            barrier.wait();
+
+            let task = ex.spawn({
+                let barrier = barrier.clone();
+                async move {
+                    barrier.wait();
+                    thread::current().name().map(String::from)
+                }
+            });
+            barrier.wait();
+            task.await
+        });
+
+    assert_eq!(name.as_deref(), Some("worker-0"));
+}
+
+#[test]
+fn propagate_panics_resumes_task_panic() {
+    let ex = Arc::new(Executor::new());
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        Builder::new()
+            .threads(1)
+            .executor(ex.clone())
+            .propagate_panics(true)
+            .build_block_on(async move {
+                // The task is detached, so its panic is only seen through the builder. The channel
+                // is closed when the panic drops the sender.
+                let (sender, receiver) = smol::channel::bounded::<()>(1);
+                ex.spawn(async move {
+                    let _sender = sender;
+                    panic!("task panicked");
+                })
+                .detach();
+                let _ = receiver.recv().await;
+            })
+    }));
+
+    let payload = result.unwrap_err();
+    assert_eq!(payload.downcast_ref::<String>().unwrap(), "task panicked");
+}
+
+#[test]
+fn on_thread_start_runs_on_every_worker() {
+    static STARTED: AtomicUsize = AtomicUsize::new(0);
+
+    Builder::new()
+        .threads(3)
+        .on_thread_start(|| {
+            STARTED.fetch_add(1, Ordering::SeqCst);
+        })
+        .build_block_on(async {});
+
+    // The workers have been joined by the time `build_block_on` returns.
+    assert_eq!(STARTED.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn local_runs_non_send_futures() {
+    let output = Builder::new().local(true).build_block_on(async {
+        let value = std::rc::Rc::new(2);
+        smol::future::yield_now().await;
+        *value * 2
+    });
+    assert_eq!(output, 4);
+}
+
+#[test]
+#[should_panic(expected = "smol_potat::spawn must be called from")]
+fn spawn_outside_runtime_panics() {
+    smol_potat::spawn(async {}).detach();
+}
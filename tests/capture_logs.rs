@@ -0,0 +1,22 @@
+#[smol_potat::test(capture_logs)]
+async fn test_capture_logs() {
+    log::info!("connected to {}", "localhost");
+    smol::future::yield_now().await;
+    log::debug!("sent {} bytes", 42);
+
+    assert!(logs.contains("connected to localhost"));
+    assert_eq!(
+        logs.lines(),
+        ["INFO connected to localhost", "DEBUG sent 42 bytes"]
+    );
+}
+
+#[smol_potat::test(capture_logs)]
+async fn test_captures_are_per_test() {
+    for _ in 0..10 {
+        log::info!("per test");
+        smol::future::yield_now().await;
+    }
+    assert_eq!(logs.lines().len(), 10);
+    assert!(!logs.contains("connected"));
+}
@@ -0,0 +1,18 @@
+#[cfg(unix)]
+#[smol_potat::main(catch_signals)]
+async fn main() {
+    use std::process::{self, Command};
+
+    // Without the handler installed by the macro, this would kill the test process.
+    let status = Command::new("kill")
+        .arg("-INT")
+        .arg(process::id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    smol::future::pending::<()>().await;
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,3 @@
+// The same test as `catch_signals`, with the handler installed through `ctrlc` instead of
+// `async-signal`.
+include!("catch_signals.rs");
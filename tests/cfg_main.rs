@@ -0,0 +1,8 @@
+// Only one of these may define the entry point, which only works if `cfg` applies to it.
+#[smol_potat::main]
+#[cfg(unix)]
+async fn main() {}
+
+#[smol_potat::main]
+#[cfg(not(unix))]
+async fn main() {}
@@ -0,0 +1,29 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static WORKERS_STARTED: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg_attr(not(feature = "auto"), allow(dead_code))]
+fn count_worker() {
+    WORKERS_STARTED.fetch_add(1, Ordering::SeqCst);
+}
+
+// The runtime is only set up with the `auto` feature, otherwise the body runs on this thread
+// and the runtime options are dropped.
+#[smol_potat::main(
+    entry = false,
+    cfg = "auto",
+    threads = 2,
+    on_thread_start = count_worker,
+    idle_poll_ms = 10
+)]
+async fn run() -> u32 {
+    smol::future::yield_now().await;
+    2
+}
+
+#[test]
+fn test_cfg() {
+    assert_eq!(run(), 2);
+    let workers = if cfg!(feature = "auto") { 2 } else { 0 };
+    assert_eq!(WORKERS_STARTED.load(Ordering::SeqCst), workers);
+}
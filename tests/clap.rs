@@ -0,0 +1,26 @@
+use std::env;
+use std::process::Command;
+
+#[derive(clap::Parser)]
+struct Args {
+    #[arg(long)]
+    name: Option<String>,
+    // Swallows the flags the test runner passes to the binary.
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, hide = true)]
+    _rest: Vec<String>,
+}
+
+#[smol_potat::main]
+async fn main(args: Args) {
+    if let Some(name) = args.name {
+        println!("hello {}", name);
+        return;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["--name", "potat"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "hello potat\n");
+}
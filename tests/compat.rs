@@ -0,0 +1,12 @@
+use std::time::Duration;
+
+#[smol_potat::test(compat)]
+async fn test_compat() {
+    // Panics with "there is no reactor running" outside of a tokio context.
+    tokio::time::sleep(Duration::from_millis(1)).await;
+}
+
+#[smol_potat::test(compat, threads = 2)]
+async fn test_compat_threads() {
+    tokio::time::sleep(Duration::from_millis(1)).await;
+}
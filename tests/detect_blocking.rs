@@ -0,0 +1,23 @@
+use std::env;
+use std::process::Command;
+use std::time::Duration;
+
+#[smol_potat::main(detect_blocking = 50, threads = 2)]
+async fn main() {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        smol_potat::spawn(async {
+            std::thread::sleep(Duration::from_millis(300));
+        })
+        .await;
+        return;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("without yielding"), "{}", stderr);
+    assert!(stderr.contains("smol::unblock"), "{}", stderr);
+}
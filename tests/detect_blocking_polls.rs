@@ -0,0 +1,51 @@
+use std::env;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+// A low threshold makes the watchdog check every millisecond, while polls keep starting on every
+// worker, so some of them start after the watchdog has read the time for its check.
+#[smol_potat::main(detect_blocking = 4, threads = 8)]
+async fn main() {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                smol_potat::spawn(async {
+                    let start = Instant::now();
+                    while start.elapsed() < Duration::from_secs(1) {
+                        smol::future::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await;
+        }
+        // The watchdog is still running, so this is reported.
+        smol_potat::spawn(async {
+            std::thread::sleep(Duration::from_millis(100));
+        })
+        .await;
+        return;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+    assert!(stderr.contains("without yielding"), "{}", stderr);
+    for line in stderr
+        .lines()
+        .filter(|line| line.contains("without yielding"))
+    {
+        let ms: u64 = line
+            .split(" for ")
+            .nth(1)
+            .and_then(|rest| rest.split("ms").next())
+            .and_then(|ms| ms.parse().ok())
+            .unwrap();
+        assert!(ms < 10_000, "{}", stderr);
+    }
+}
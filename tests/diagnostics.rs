@@ -0,0 +1,43 @@
+use smol_potat::TaskState;
+
+#[smol_potat::main(diagnostics, threads = 2)]
+async fn main() {
+    let (sender, receiver) = smol::channel::bounded::<()>(1);
+    let waiting = smol_potat::spawn_named("fetch", async move {
+        let _ = receiver.recv().await;
+    });
+    smol::future::yield_now().await;
+    let running = smol_potat::spawn(async { smol_potat::dump_tasks() });
+    let tasks = running.await;
+    assert_eq!(
+        tasks,
+        [
+            ("fetch".to_string(), TaskState::Waiting),
+            ("<unnamed>".to_string(), TaskState::Running),
+        ]
+    );
+
+    drop(sender);
+    waiting.await;
+    assert!(smol_potat::dump_tasks().is_empty());
+
+    // The `SIGQUIT` handler is only installed with `async-signal`.
+    #[cfg(all(unix, feature = "async-signal", not(feature = "ctrlc-integration")))]
+    if std::env::var_os("SMOL_POTAT_CHILD").is_some() {
+        let status = std::process::Command::new("kill")
+            .arg("-QUIT")
+            .arg(std::process::id().to_string())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        smol::Timer::after(std::time::Duration::from_millis(100)).await;
+    } else {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .env("SMOL_POTAT_CHILD", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains("task dump: 0 pending tasks"), "{}", stderr);
+    }
+}
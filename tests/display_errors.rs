@@ -0,0 +1,30 @@
+use std::env;
+use std::fmt;
+use std::process::Command;
+
+// Only `Display`, so returning it from a plain main would not compile.
+struct UsageError(&'static str);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "usage: {}", self.0)
+    }
+}
+
+#[smol_potat::main(display_errors)]
+async fn main() -> Result<(), UsageError> {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        return Err(UsageError("app <config>"));
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error: usage: app <config>\n",
+    );
+    Ok(())
+}
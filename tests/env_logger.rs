@@ -0,0 +1,5 @@
+#[smol_potat::test(env_logger)]
+async fn test_env_logger() {
+    log::error!("running");
+    assert_ne!(log::max_level(), log::LevelFilter::Off);
+}
@@ -0,0 +1,17 @@
+use std::env;
+use std::error::Error;
+use std::process::Command;
+
+#[smol_potat::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        return Err("async main failed".into());
+    }
+
+    let output = Command::new(env::current_exe()?)
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()?;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?.contains("async main failed"));
+    Ok(())
+}
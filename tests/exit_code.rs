@@ -0,0 +1,16 @@
+use std::env;
+use std::process::{Command, ExitCode};
+
+#[smol_potat::main]
+async fn main() -> ExitCode {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        return ExitCode::from(42);
+    }
+
+    let status = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(42));
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,17 @@
+use std::env;
+use std::process::Command;
+
+#[smol_potat::main(exit_code)]
+async fn main() -> i32 {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        smol::future::yield_now().await;
+        return 7;
+    }
+
+    let status = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(7));
+    0
+}
@@ -0,0 +1,29 @@
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+#[smol_potat::main(expose_executor, threads = 2, thread_name = "worker")]
+async fn main() {
+    let main_thread = thread::current().id();
+
+    // Both tasks can only get through the barrier by running on the two workers at once.
+    let barrier = Arc::new(Barrier::new(3));
+    let tasks: Vec<_> = (0..2)
+        .map(|_| {
+            let barrier = barrier.clone();
+            executor.spawn(async move {
+                barrier.wait();
+                let thread = thread::current();
+                assert!(thread.name().unwrap().starts_with("worker-"));
+                thread.id()
+            })
+        })
+        .collect();
+    barrier.wait();
+
+    let mut ids = Vec::new();
+    for task in tasks {
+        ids.push(task.await);
+    }
+    assert!(ids.iter().all(|id| *id != main_thread));
+    assert_ne!(ids[0], ids[1]);
+}
@@ -0,0 +1,20 @@
+use std::sync::Mutex;
+
+static EVENTS: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+async fn flush() {
+    let task = smol_potat::spawn(async { EVENTS.lock().unwrap().push("flushed") });
+    task.await;
+}
+
+#[smol_potat::main(finalizer = flush, threads = 2)]
+async fn main() -> Result<(), &'static str> {
+    EVENTS.lock().unwrap().push("body");
+    Err("failed")
+}
+
+#[test]
+fn test_finalizer() {
+    assert_eq!(main(), Err("failed"));
+    assert_eq!(*EVENTS.lock().unwrap(), ["body", "flushed"]);
+}
@@ -0,0 +1,5 @@
+#[smol_potat::main(executor = "global")]
+async fn main() {
+    let task = async_global_executor::spawn(async { 1 + 2 });
+    assert_eq!(task.await, 3);
+}
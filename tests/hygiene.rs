@@ -1,5 +1,6 @@
 #![no_implicit_prelude]
 
+#[allow(unused_imports)]
 use ::std::panic;
 
 #[::smol_potat::main]
@@ -12,3 +13,17 @@ async fn test() -> ::std::io::Result<()> {
     ::std::assert_eq!(2 * 2, 4);
     ::std::result::Result::Ok(())
 }
+
+#[::smol_potat::test(timeout = 1000)]
+async fn test_timeout() {}
+
+#[::smol_potat::test(local)]
+async fn test_local() {}
+
+#[::smol_potat::test(retries = 1)]
+async fn test_retries() {}
+
+#[::smol_potat::test(pretty_errors)]
+async fn test_pretty_errors() -> ::std::result::Result<(), ::std::string::String> {
+    ::std::result::Result::Ok(())
+}
@@ -0,0 +1,20 @@
+use std::time::{Duration, Instant};
+
+#[smol_potat::main(idle_poll_ms = 50, threads = 2)]
+async fn main() -> (Duration, Duration) {
+    let start = Instant::now();
+    smol_potat::spawn(async {}).await;
+    let spawned = start.elapsed();
+
+    let start = Instant::now();
+    smol_potat::time::sleep(Duration::from_millis(10)).await;
+    (spawned, start.elapsed())
+}
+
+#[test]
+fn test_idle_poll() {
+    let (spawned, slept) = main();
+    assert!(spawned < Duration::from_millis(50), "{:?}", spawned);
+    assert!(slept >= Duration::from_millis(10), "{:?}", slept);
+    assert!(slept < Duration::from_millis(500), "{:?}", slept);
+}
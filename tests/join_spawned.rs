@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static DONE: AtomicBool = AtomicBool::new(false);
+
+#[smol_potat::main(join_spawned, threads = 2)]
+async fn main() {
+    smol_potat::spawn(async {
+        smol::Timer::after(Duration::from_millis(50)).await;
+        // Spawned after main's body has completed, and joined as well.
+        smol_potat::spawn(async {
+            smol::future::yield_now().await;
+            DONE.store(true, Ordering::SeqCst);
+        })
+        .detach();
+    })
+    .detach();
+}
+
+#[test]
+fn test_join_spawned() {
+    main();
+    assert!(DONE.load(Ordering::SeqCst));
+}
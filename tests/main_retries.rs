@@ -0,0 +1,35 @@
+use std::env;
+use std::process::Command;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+
+#[smol_potat::main(retries = 2, backoff_ms = 10)]
+async fn main() -> Result<(), String> {
+    if let Ok(succeed_on) = env::var("SMOL_POTAT_CHILD") {
+        let attempt = ATTEMPTS.fetch_add(1, Ordering::SeqCst) + 1;
+        if attempt.to_string() != succeed_on {
+            return Err(format!("attempt {} failed", attempt));
+        }
+        return Ok(());
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "3")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "{}", stderr);
+    assert!(
+        stderr.contains("main failed on attempt 2 of 3, retrying in 10ms: \"attempt 2 failed\"")
+    );
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "4")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("Error: \"attempt 3 failed\""), "{}", stderr);
+    Ok(())
+}
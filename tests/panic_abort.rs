@@ -0,0 +1,33 @@
+use std::env;
+use std::process::Command;
+use std::sync::{Arc, Barrier};
+
+#[smol_potat::main(expose_executor, panic = "abort")]
+async fn main() {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        // The barrier keeps the main thread blocked, so the task panics on the worker.
+        let barrier = Arc::new(Barrier::new(2));
+        executor
+            .spawn({
+                let barrier = barrier.clone();
+                async move {
+                    barrier.wait();
+                    panic!("task panicked");
+                }
+            })
+            .detach();
+        barrier.wait();
+        smol::future::pending::<()>().await;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(output.status.signal(), Some(6));
+    }
+}
@@ -0,0 +1,25 @@
+use std::env;
+use std::panic::PanicHookInfo;
+use std::process::{self, Command};
+
+fn exit_with_code(info: &PanicHookInfo) {
+    eprintln!("custom hook: {}", info);
+    process::exit(3);
+}
+
+#[smol_potat::main(panic_hook = exit_with_code, threads = 2)]
+async fn main() {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        smol_potat::spawn(async { panic!("task panicked") }).await;
+        unreachable!("the hook exits the process");
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("custom hook: "), "{}", stderr);
+    assert!(stderr.contains("task panicked"), "{}", stderr);
+}
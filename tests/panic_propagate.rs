@@ -0,0 +1,26 @@
+use std::env;
+use std::process::Command;
+
+#[smol_potat::main(panic = "propagate", threads = 2)]
+async fn main() {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        // The channel is closed when the panic drops the sender.
+        let (sender, receiver) = smol::channel::bounded::<()>(1);
+        smol_potat::spawn(async move {
+            let _sender = sender;
+            panic!("task panicked");
+        })
+        .detach();
+        let _ = receiver.recv().await;
+        return;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(101));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("task panicked"));
+}
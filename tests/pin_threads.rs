@@ -0,0 +1,15 @@
+#[smol_potat::test(threads = 2, pin_threads)]
+async fn test_pin_threads() {
+    assert_eq!(smol_potat::spawn(async { 1 + 2 }).await, 3);
+}
+
+#[test]
+fn builder_pin_threads() {
+    let ex = std::sync::Arc::new(smol_potat::Executor::new());
+    let output = smol_potat::Builder::new()
+        .threads(4)
+        .executor(ex.clone())
+        .pin_threads(true)
+        .build_block_on(async { ex.spawn(async { 1 + 2 }).await });
+    assert_eq!(output, 3);
+}
@@ -0,0 +1,55 @@
+use std::cell::Cell;
+
+use proptest::collection::vec;
+
+#[smol_potat::test(proptest(cases = 50))]
+async fn test_proptest(#[strategy(0u32..100)] a: u32, b: bool) {
+    let doubled = smol::unblock(move || a * 2).await;
+    assert_eq!(doubled, a * 2);
+    assert!(a < 100);
+    let _ = b;
+}
+
+#[smol_potat::test(proptest)]
+async fn test_proptest_result(
+    #[strategy(vec(0u8..10, 0..8))] bytes: Vec<u8>,
+) -> Result<(), String> {
+    smol::future::yield_now().await;
+    match bytes.iter().all(|byte| *byte < 10) {
+        true => Ok(()),
+        false => Err(format!("{:?}", bytes)),
+    }
+}
+
+#[smol_potat::test(proptest(cases = 20), threads = 2)]
+async fn test_proptest_threads(#[strategy(1usize..4)] tasks: usize) {
+    let handles: Vec<_> = (0..tasks)
+        .map(|i| smol_potat::spawn(async move { i }))
+        .collect();
+    let mut sum = 0;
+    for handle in handles {
+        sum += handle.await;
+    }
+    assert_eq!(sum, tasks * (tasks - 1) / 2);
+}
+
+thread_local! {
+    static CASES: Cell<u32> = const { Cell::new(0) };
+    static POLLED: Cell<u32> = const { Cell::new(0) };
+}
+
+#[smol_potat::test(proptest(cases = 50))]
+async fn counted_cases(value: u64) {
+    CASES.with(|cases| cases.set(cases.get() + 1));
+    smol::future::yield_now().await;
+    POLLED.with(|polled| polled.set(polled.get() + 1));
+    let _ = value;
+}
+
+#[test]
+fn test_proptest_cases() {
+    // The runner runs the cases on the current thread, so the counts only see this call.
+    counted_cases();
+    assert_eq!(CASES.with(Cell::get), 50);
+    assert_eq!(POLLED.with(Cell::get), 50);
+}
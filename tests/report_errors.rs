@@ -0,0 +1,38 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::process::Command;
+
+#[derive(Debug)]
+struct StartupError(std::io::Error);
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("failed to start")
+    }
+}
+
+impl Error for StartupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[smol_potat::main(report_errors)]
+async fn main() -> Result<(), StartupError> {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        let err = std::io::Error::other("connection refused");
+        return Err(StartupError(err));
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Error: failed to start\n\nCaused by:\n    connection refused\n",
+    );
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::sync::Arc;
+
+use smol_potat::Runtime;
+
+#[test]
+fn test_new() {
+    let rt = Runtime::new();
+    let task = rt.spawn(async { 1 + 2 });
+    assert_eq!(rt.block_on(task), 3);
+}
+
+#[test]
+fn test_block_on_twice() {
+    let rt = Runtime::with_threads(2);
+    assert_eq!(rt.block_on(async { 1 }), 1);
+    // The workers keep running between the two calls.
+    let task = rt.spawn(async { 2 });
+    assert_eq!(rt.block_on(task), 2);
+}
+
+#[test]
+fn test_workers_stop_on_drop() {
+    let rt = Runtime::with_threads(2);
+    // A task that never completes stays on the executor until the runtime drops it.
+    rt.spawn(smol::future::pending::<()>()).detach();
+    let executor = Arc::downgrade(rt.executor());
+    drop(rt);
+    // Every worker held the executor, so it is only gone once they have all stopped.
+    assert!(executor.upgrade().is_none());
+}
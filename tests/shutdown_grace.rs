@@ -0,0 +1,47 @@
+#[cfg(unix)]
+#[smol_potat::main(catch_signals, shutdown_grace_ms = 500)]
+async fn main() {
+    use std::env;
+    use std::process::{self, Command};
+    use std::time::{Duration, Instant};
+
+    match env::var("SMOL_POTAT_CHILD").as_deref() {
+        Ok(mode) => {
+            let status = Command::new("kill")
+                .arg("-INT")
+                .arg(process::id().to_string())
+                .status()
+                .unwrap();
+            assert!(status.success());
+
+            smol_potat::shutdown_signal().await;
+            println!("shutting down");
+            if mode == "finish" {
+                smol::Timer::after(Duration::from_millis(10)).await;
+                println!("finished");
+            } else {
+                smol::future::pending::<()>().await;
+            }
+        }
+        Err(_) => {
+            let output = Command::new(env::current_exe().unwrap())
+                .env("SMOL_POTAT_CHILD", "finish")
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            assert_eq!(output.stdout, b"shutting down\nfinished\n");
+
+            let start = Instant::now();
+            let output = Command::new(env::current_exe().unwrap())
+                .env("SMOL_POTAT_CHILD", "hang")
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            assert_eq!(output.stdout, b"shutting down\n");
+            assert!(start.elapsed() >= Duration::from_millis(500));
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {}
@@ -0,0 +1,31 @@
+use std::env;
+use std::error::Error;
+use std::process::Command;
+
+#[smol_potat::main(entry = false, threads = "respect_env")]
+async fn respect_env() {}
+
+#[smol_potat::main(threads = 2)]
+async fn main() -> Result<(), Box<dyn Error>> {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        respect_env();
+        return Ok(());
+    }
+
+    let output = Command::new(env::current_exe()?)
+        .env("SMOL_POTAT_CHILD", "1")
+        .env("SMOL_THREADS", "3")
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("SMOL_THREADS=3 is ignored"));
+
+    // An invalid value is ignored by `respect_env` too, rather than failing at startup.
+    let output = Command::new(env::current_exe()?)
+        .env("SMOL_POTAT_CHILD", "1")
+        .env("SMOL_THREADS", "abc")
+        .output()?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("SMOL_THREADS=abc is ignored, it is not a positive number of threads"));
+    Ok(())
+}
@@ -0,0 +1,22 @@
+// Kept apart from the other tests, so that no test with worker threads is driving the shared
+// executor when the threadless test spawns onto it.
+
+#[smol_potat::test(threads = 2)]
+#[ignore = "run by threadless_after_threaded"]
+async fn threaded() {
+    assert_eq!(smol_potat::spawn(async { 1 }).await, 1);
+}
+
+#[smol_potat::test]
+#[ignore = "panics on purpose, run by threadless_after_threaded"]
+async fn threadless() {
+    smol_potat::spawn(async {}).detach();
+}
+
+#[test]
+#[should_panic(expected = "smol_potat::spawn must be called from")]
+fn threadless_after_threaded() {
+    // The shared executor exists by now, but nothing drives it any more.
+    threaded();
+    threadless();
+}
@@ -0,0 +1,37 @@
+use std::env;
+use std::process::Command;
+
+#[smol_potat::main(task_metrics, threads = 2)]
+async fn main() {
+    if env::var_os("SMOL_POTAT_CHILD").is_some() {
+        // Every task waits for the channel to close, so all of them are pending at once.
+        let (sender, receiver) = smol::channel::bounded::<()>(1);
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let receiver = receiver.clone();
+                smol_potat::spawn(async move {
+                    let _ = receiver.recv().await;
+                })
+            })
+            .collect();
+        drop(sender);
+        for task in tasks {
+            task.await;
+        }
+        // Cancelled before it could complete.
+        drop(smol_potat::spawn(smol::future::pending::<()>()));
+        return;
+    }
+
+    let output = Command::new(env::current_exe().unwrap())
+        .env("SMOL_POTAT_CHILD", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("task metrics: 11 spawned, 10 completed, at most 10 at once"),
+        "{}",
+        stderr
+    );
+}
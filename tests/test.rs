@@ -1,3 +1,5 @@
+#![allow(clippy::eq_op)]
+
 #[smol_potat::main]
 async fn main() -> std::io::Result<()> {
     assert_eq!(2 * 2, 4);
@@ -9,3 +11,862 @@ async fn test() -> std::io::Result<()> {
     assert_eq!(2 * 2, 4);
     Ok(())
 }
+
+#[smol_potat::test(timeout = 5000)]
+async fn test_timeout() {
+    smol::Timer::after(std::time::Duration::from_millis(10)).await;
+}
+
+#[smol_potat::test(timeout = 10)]
+#[should_panic(expected = "test 'test_timeout_expired' timed out after 10ms")]
+async fn test_timeout_expired() {
+    smol::Timer::after(std::time::Duration::from_secs(10)).await;
+}
+
+#[should_panic(expected = "boom")]
+#[smol_potat::test]
+async fn test_should_panic_above() {
+    smol::future::yield_now().await;
+    panic!("boom");
+}
+
+#[smol_potat::test(local)]
+async fn test_local() {
+    let value = std::rc::Rc::new(4);
+    smol::future::yield_now().await;
+    assert_eq!(*value, 4);
+}
+
+#[rustfmt::skip]
+#[smol_potat::test(local, timeout = 1000,)]
+async fn test_trailing_comma() {}
+
+// Both forms of a unit return type expand to the same code, explicit `()` included.
+#[smol_potat::test(retries = 1)]
+async fn test_unit_implicit() {}
+
+#[smol_potat::test(retries = 1)]
+async fn test_unit_explicit() -> () {}
+
+static RETRIED_PANICS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[smol_potat::test(retries = 3)]
+async fn test_retries_panic() {
+    let attempt = RETRIED_PANICS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+    assert!(attempt > 2, "attempt {} failed", attempt);
+}
+
+static RETRIED_ERRORS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+#[smol_potat::test(retries = 1)]
+async fn test_retries_err() -> Result<(), String> {
+    match RETRIED_ERRORS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+        0 => Err("first attempt failed".to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[smol_potat::test(retries = 2)]
+#[should_panic(expected = "always fails")]
+async fn test_retries_exhausted() {
+    panic!("always fails");
+}
+
+#[smol_potat::test(threads = 2)]
+async fn test_threads() -> std::io::Result<()> {
+    let (sender, receiver) = smol::channel::bounded(1);
+    std::thread::spawn(move || sender.send_blocking(42));
+    assert_eq!(receiver.recv().await.ok(), Some(42));
+    Ok(())
+}
+
+#[smol_potat::test(worker_threads = 2)]
+async fn test_worker_threads() {}
+
+#[smol_potat::test(threads = 0)]
+async fn test_threads_zero_is_auto() {
+    assert_eq!(smol_potat::spawn(async { 1 + 2 }).await, 3);
+}
+
+#[smol_potat::test(threads = 2)]
+async fn test_spawn() {
+    let (sender, receiver) = smol::channel::bounded(1);
+    smol_potat::spawn(async move { sender.send(42).await }).detach();
+    assert_eq!(receiver.recv().await.ok(), Some(42));
+}
+
+#[smol_potat::test(threads = 2)]
+async fn test_spawn_named() {
+    assert_eq!(smol_potat::spawn_named("double", double(21)).await, 42);
+}
+
+#[smol_potat::test(threads = 2, max_tasks = 4)]
+async fn test_max_tasks() {
+    for i in 0..4 {
+        assert_eq!(smol_potat::spawn(async move { i }).await, i);
+    }
+}
+
+#[smol_potat::test(threads = 2, max_tasks = 3)]
+#[should_panic(expected = "the test spawned more than 3 tasks")]
+async fn test_max_tasks_exceeded() {
+    // Tasks spawned by the test's tasks count too.
+    smol_potat::spawn(async {
+        for _ in 0..3 {
+            smol_potat::spawn(async {}).await;
+        }
+    })
+    .await;
+}
+
+#[smol_potat::test(seed = 12345)]
+async fn test_seed() {
+    let mut rng = fastrand::Rng::with_seed(12345);
+    for _ in 0..8 {
+        smol::future::yield_now().await;
+        assert_eq!(fastrand::u64(..), rng.u64(..));
+    }
+}
+
+#[smol_potat::test(seed = 12345, threads = 2)]
+async fn test_seed_threads() {
+    let mut rng = fastrand::Rng::with_seed(12345);
+    let values: Vec<u32> = (0..8).map(|_| fastrand::u32(..)).collect();
+    assert_eq!(values, (0..8).map(|_| rng.u32(..)).collect::<Vec<_>>());
+}
+
+#[smol_potat::test(shared_executor)]
+async fn test_shared_executor() {
+    let (sender, receiver) = smol::channel::unbounded::<u32>();
+    let (doubled_sender, doubled) = smol::channel::unbounded::<u32>();
+    runtime.block_on(async {
+        executor
+            .spawn(async move {
+                let value = receiver.recv().await.unwrap();
+                doubled_sender.send(value * 2).await.unwrap();
+            })
+            .detach();
+    });
+    let doubled = runtime.block_on(async {
+        sender.send(21).await.unwrap();
+        doubled.recv().await.unwrap()
+    });
+    assert_eq!(doubled, 42);
+}
+
+#[smol_potat::test(threads = 2)]
+async fn test_idle() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let done = Arc::new(AtomicBool::new(false));
+    let task_done = done.clone();
+    smol_potat::spawn(async move {
+        smol::future::yield_now().await;
+        task_done.store(true, Ordering::SeqCst);
+    })
+    .detach();
+    smol_potat::idle().await;
+    assert!(done.load(Ordering::SeqCst));
+}
+
+#[smol_potat::test(threads = 2, deadlock_detect)]
+#[should_panic(expected = "test 'test_deadlock_detect' deadlocked: no progress for 1000ms")]
+async fn test_deadlock_detect() {
+    let (first_sender, first_receiver) = smol::channel::bounded::<()>(1);
+    let (second_sender, second_receiver) = smol::channel::bounded::<()>(1);
+    // Each task waits for the other one to send first.
+    let first = smol_potat::spawn(async move {
+        first_receiver.recv().await.ok();
+        second_sender.send(()).await.ok();
+    });
+    let second = smol_potat::spawn(async move {
+        second_receiver.recv().await.ok();
+        first_sender.send(()).await.ok();
+    });
+    first.await;
+    second.await;
+}
+
+#[smol_potat::test(threads = 2, deadlock_detect)]
+async fn test_deadlock_detect_progress() {
+    for _ in 0..3 {
+        smol::Timer::after(std::time::Duration::from_millis(400)).await;
+    }
+}
+
+#[smol_potat::test(threads = 2, deadlock_detect = 2000)]
+async fn test_deadlock_detect_window() {
+    // Longer than the default window, but not this one.
+    smol::Timer::after(std::time::Duration::from_millis(1200)).await;
+}
+
+#[smol_potat::test(threads = 2, deadlock_detect = 300)]
+#[should_panic(expected = "deadlocked: no progress for 300ms, with 1 spawned tasks pending")]
+async fn test_deadlock_detect_other_tasks() {
+    // Spawned from outside the test, like the tasks of the other tests sharing the workers, and
+    // busy for longer than the window.
+    std::thread::spawn(|| {
+        smol_potat::spawn(async {
+            for _ in 0..100 {
+                smol::Timer::after(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .detach();
+    })
+    .join()
+    .unwrap();
+
+    let (_sender, receiver) = smol::channel::bounded::<()>(1);
+    smol_potat::spawn(async move {
+        receiver.recv().await.ok();
+    })
+    .await;
+}
+
+#[smol_potat::test(threads = 2)]
+#[should_panic(expected = "panicked in a spawned task")]
+async fn test_should_panic_spawned() {
+    use smol::future::FutureExt;
+
+    let task = smol_potat::spawn(async {
+        panic!("panicked in a spawned task");
+    });
+    // Awaited without resuming the panic, so only its propagation can fail the test.
+    let _ = std::panic::AssertUnwindSafe(task).catch_unwind().await;
+}
+
+#[smol_potat::test(threads = 2)]
+#[should_panic(expected = "panicked in a nested task")]
+async fn test_should_panic_nested_spawn() {
+    use smol::future::FutureExt;
+
+    smol_potat::spawn(async {
+        let task = smol_potat::spawn(async {
+            panic!("panicked in a nested task");
+        });
+        let _ = std::panic::AssertUnwindSafe(task).catch_unwind().await;
+    })
+    .await;
+}
+
+#[smol_potat::test(threads = 2)]
+#[should_panic(expected = "panicked in a task of this test")]
+async fn test_should_panic_own_tasks() {
+    use smol::future::FutureExt;
+
+    // Spawned from outside the test, like the tasks of the other tests sharing the workers.
+    let task = std::thread::spawn(|| {
+        smol_potat::spawn(async {
+            panic!("panicked in a task of another test");
+        })
+    })
+    .join()
+    .unwrap();
+    let _ = std::panic::AssertUnwindSafe(task).catch_unwind().await;
+
+    let task = smol_potat::spawn(async {
+        panic!("panicked in a task of this test");
+    });
+    let _ = std::panic::AssertUnwindSafe(task).catch_unwind().await;
+}
+
+#[smol_potat::test(start_paused)]
+async fn test_start_paused_waits_for_other_work() {
+    use smol::future::FutureExt;
+    use smol_potat::time;
+    use std::time::Duration;
+
+    let (sender, receiver) = smol::channel::bounded(1);
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        sender.send_blocking(1)
+    });
+    // The clock stays put while the test waits on the channel too.
+    let received = async { receiver.recv().await.ok() }
+        .or(async {
+            time::sleep(Duration::from_secs(60)).await;
+            None
+        })
+        .await;
+    assert_eq!(received, Some(1));
+}
+
+#[smol_potat::test(threads = "respect_env")]
+async fn test_threads_env() {}
+
+#[smol_potat::test(threads = "container")]
+async fn test_threads_container() {
+    let threads = smol_potat::__private::container_threads();
+    assert!(threads >= 1 && threads <= smol_potat::__private::num_cpus::get().max(1));
+}
+
+#[smol_potat::test(matrix_threads = [1, 4])]
+async fn test_matrix() {
+    let (sender, receiver) = smol::channel::bounded(1);
+    std::thread::spawn(move || sender.send_blocking(42));
+    assert_eq!(receiver.recv().await.ok(), Some(42));
+}
+
+#[smol_potat::test(threads = 2, thread_name = "worker", stack_size = 8388608)]
+async fn test_worker_config() {}
+
+#[smol_potat::test(start_paused)]
+async fn test_start_paused() {
+    use smol_potat::time;
+    use std::time::{Duration, Instant};
+
+    let real = Instant::now();
+    let start = time::now();
+    let short = time::sleep(Duration::from_secs(60));
+    let long = async {
+        time::sleep(Duration::from_secs(3600)).await;
+        time::now()
+    };
+    let (_, end) = smol::future::zip(short, long).await;
+
+    assert_eq!(end - start, Duration::from_secs(3600));
+    assert!(real.elapsed() < Duration::from_secs(10));
+}
+
+static WORKERS_STARTED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+fn count_worker() {
+    WORKERS_STARTED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+}
+
+#[smol_potat::test(threads = 2, on_thread_start = count_worker, timeout = 5000)]
+async fn test_on_thread_start() {
+    while WORKERS_STARTED.load(std::sync::atomic::Ordering::SeqCst) < 2 {
+        smol::Timer::after(std::time::Duration::from_millis(1)).await;
+    }
+}
+
+static FN_WORKERS_STARTED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+fn three_threads() -> usize {
+    3
+}
+
+fn count_fn_worker() {
+    FN_WORKERS_STARTED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+}
+
+#[smol_potat::test(threads_fn = three_threads, on_thread_start = count_fn_worker)]
+async fn test_threads_fn() {
+    // Every worker has to be busy at once to get past the barrier.
+    let barrier = std::sync::Arc::new(std::sync::Barrier::new(3));
+    let tasks: Vec<_> = (0..3)
+        .map(|_| {
+            let barrier = barrier.clone();
+            smol_potat::spawn(async move {
+                barrier.wait();
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await;
+    }
+    assert_eq!(
+        FN_WORKERS_STARTED.load(std::sync::atomic::Ordering::SeqCst),
+        3
+    );
+}
+
+static EXPR_WORKERS_STARTED: std::sync::atomic::AtomicUsize =
+    std::sync::atomic::AtomicUsize::new(0);
+
+fn worker_count(workers: u8) -> u8 {
+    workers + 1
+}
+
+fn count_expr_worker() {
+    EXPR_WORKERS_STARTED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+}
+
+#[smol_potat::test(threads = worker_count(2), on_thread_start = count_expr_worker)]
+async fn test_threads_expr() {
+    // Every worker has to be busy at once to get past the barrier.
+    let barrier = std::sync::Arc::new(std::sync::Barrier::new(3));
+    let tasks: Vec<_> = (0..3)
+        .map(|_| {
+            let barrier = barrier.clone();
+            smol_potat::spawn(async move {
+                barrier.wait();
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await;
+    }
+    assert_eq!(
+        EXPR_WORKERS_STARTED.load(std::sync::atomic::Ordering::SeqCst),
+        3
+    );
+}
+
+#[smol_potat::test(reactor = "dedicated")]
+async fn test_dedicated_reactor() {
+    smol::Timer::after(std::time::Duration::from_millis(1)).await;
+}
+
+#[test]
+fn test_matrix_names() {
+    let _: fn() = test_matrix_threads_1;
+    let _: fn() = test_matrix_threads_4;
+}
+
+#[smol_potat::test(cases = [(1, 2), (3, 4)])]
+async fn test_cases() {
+    let (a, b) = case;
+    smol::future::yield_now().await;
+    assert_eq!(a + 1, b);
+}
+
+#[smol_potat::test(cases = [1, 2], matrix_threads = [1, 2])]
+async fn test_cases_matrix() {
+    assert_eq!(smol_potat::spawn(async move { case * 2 }).await % 2, 0);
+}
+
+#[test]
+fn test_cases_names() {
+    let _: fn() = test_cases_case_0;
+    let _: fn() = test_cases_case_1;
+    let _: fn() = test_cases_matrix_case_0_threads_1;
+    let _: fn() = test_cases_matrix_case_1_threads_2;
+}
+
+trait Codec {
+    fn name() -> &'static str;
+}
+
+struct Json;
+
+impl Codec for Json {
+    fn name() -> &'static str {
+        "json"
+    }
+}
+
+#[smol_potat::test]
+async fn test_generic<T = Json, const N: usize = 2>() -> std::io::Result<()>
+where
+    T: Codec,
+{
+    assert_eq!(T::name(), "json");
+    assert_eq!(N, 2);
+    Ok(())
+}
+
+#[smol_potat::test]
+async fn test_lifetimes<'a>() -> Result<(), &'a str>
+where
+    Json: Codec,
+{
+    let name: &'a str = Json::name();
+    assert_eq!(name, "json");
+    Ok(())
+}
+
+async fn double(value: u32) -> u32 {
+    smol::future::yield_now().await;
+    value * 2
+}
+
+#[smol_potat::test]
+fn test_returns_future() -> impl std::future::Future<Output = std::io::Result<()>> {
+    async {
+        assert_eq!(double(2).await, 4);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct ConnectError(std::io::Error);
+
+impl std::fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("failed to connect")
+    }
+}
+
+impl std::error::Error for ConnectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+#[smol_potat::test(pretty_errors)]
+async fn test_pretty_errors() -> Result<(), ConnectError> {
+    Ok(())
+}
+
+#[smol_potat::test(pretty_errors)]
+#[should_panic(expected = "failed to connect\n\nCaused by:\n    connection refused")]
+async fn test_pretty_errors_chain() -> Result<(), ConnectError> {
+    let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "connection refused");
+    Err(ConnectError(refused))
+}
+
+mod renamed {
+    use smol_potat as other_smol_potat;
+
+    #[other_smol_potat::test(crate = other_smol_potat)]
+    async fn test_crate_path() {}
+
+    #[other_smol_potat::test(crate = "other_smol_potat")]
+    async fn test_crate_string() {}
+}
+
+#[derive(Debug, PartialEq)]
+struct PlainError;
+
+#[smol_potat::test]
+async fn test_debug_error() -> Result<(), PlainError> {
+    Ok(())
+}
+
+#[smol_potat::test]
+#[ignore = "fails on purpose, run by test_debug_error_reported"]
+async fn test_debug_error_fails() -> Result<(), PlainError> {
+    Err(PlainError)
+}
+
+#[test]
+fn test_debug_error_reported() {
+    use std::process::{ExitCode, Termination};
+
+    assert_eq!(test_debug_error_fails(), Err(PlainError));
+    assert_eq!(test_debug_error_fails().report(), ExitCode::FAILURE);
+}
+
+#[smol_potat::test(retries = 1)]
+async fn test_exit_code() -> std::process::ExitCode {
+    std::process::ExitCode::SUCCESS
+}
+
+mod first {
+    #[smol_potat::test]
+    async fn run() {}
+}
+
+mod second {
+    #[smol_potat::test]
+    async fn run() {}
+}
+
+mod shadowing {
+    fn attempt() -> u32 {
+        7
+    }
+
+    const TIMEOUT: u64 = 5;
+
+    fn timeout() -> u64 {
+        TIMEOUT
+    }
+
+    #[smol_potat::test(retries = 1, timeout = 1000)]
+    async fn test_locals_do_not_shadow() {
+        assert_eq!(attempt(), 7);
+        assert_eq!(timeout(), 5);
+    }
+}
+
+mod env_timeout {
+    use std::process::Command;
+    use std::time::Duration;
+
+    #[smol_potat::test]
+    #[ignore = "run by test_env_timeout"]
+    async fn hangs() {
+        async_io::Timer::after(Duration::from_secs(60)).await;
+    }
+
+    #[smol_potat::test]
+    #[ignore = "run by test_env_timeout"]
+    async fn sleeps() {
+        async_io::Timer::after(Duration::from_millis(100)).await;
+    }
+
+    #[smol_potat::test(timeout = 5000)]
+    #[ignore = "run by test_env_timeout"]
+    async fn overridden() {
+        async_io::Timer::after(Duration::from_millis(100)).await;
+    }
+
+    fn run(test: &str, timeout: &str) -> (bool, String) {
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["--ignored", "--exact", test])
+            .env("SMOL_POTAT_TEST_TIMEOUT", timeout)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        (output.status.success(), stdout)
+    }
+
+    #[test]
+    fn test_env_timeout() {
+        let (success, stdout) = run("env_timeout::hangs", "10");
+        assert!(!success);
+        assert!(
+            stdout.contains("test 'hangs' timed out after 10ms"),
+            "{}",
+            stdout
+        );
+
+        assert!(run("env_timeout::overridden", "10").0);
+        assert!(run("env_timeout::sleeps", "soon").0);
+    }
+}
+
+mod ignore {
+    use std::process::Command;
+
+    #[smol_potat::test(ignore = "needs a database")]
+    async fn with_reason() {
+        panic!("ignored tests don't run");
+    }
+
+    #[smol_potat::test(ignore)]
+    async fn without_reason() {
+        panic!("ignored tests don't run");
+    }
+
+    #[test]
+    fn test_ignore() {
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args(["ignore::with", "ignore::without"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("test ignore::with_reason ... ignored, needs a database"));
+        assert!(stdout.contains("test ignore::without_reason ... ignored\n"));
+    }
+}
+
+#[smol_potat::main(entry = false, threads = 2)]
+async fn start() -> u32 {
+    smol_potat::spawn(async { 1 + 2 }).await
+}
+
+#[test]
+fn test_entry_false() {
+    assert_eq!(start(), 3);
+}
+
+#[smol_potat::main(entry = false, executor = "local", threads = 2)]
+async fn start_own_executor() -> u32 {
+    smol::Timer::after(std::time::Duration::from_millis(1)).await;
+    1 + 2
+}
+
+#[test]
+fn test_executor_local() {
+    // Each run creates its executor afresh.
+    assert_eq!(start_own_executor(), 3);
+    assert_eq!(start_own_executor(), 3);
+}
+
+static RECURSIONS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+// Inside the body, the function's name refers to the generated wrapper, not the async function,
+// so a recursive call starts a nested runtime.
+#[smol_potat::main(entry = false)]
+async fn recurse() -> u32 {
+    match RECURSIONS.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+        0 | 1 => recurse() + 1,
+        _ => 0,
+    }
+}
+
+#[test]
+fn test_entry_recursion() {
+    assert_eq!(recurse(), 2);
+}
+
+#[smol_potat::main(entry = false)]
+async fn capture_backtrace() -> String {
+    std::backtrace::Backtrace::force_capture().to_string()
+}
+
+#[test]
+fn test_inner_function_name() {
+    // The async function is named apart from the wrapper, so the two can be told apart.
+    let backtrace = capture_backtrace();
+    assert!(
+        backtrace.contains("capture_backtrace::__smol_potat_main"),
+        "{}",
+        backtrace
+    );
+}
+
+#[smol_potat::main(entry = false, fair)]
+async fn fair_main() -> u32 {
+    let task = smol_potat::spawn(async { 1 });
+    let mut sum = 0;
+    for _ in 0..100 {
+        smol::future::yield_now().await;
+        sum += 1;
+    }
+    sum + task.await
+}
+
+#[test]
+fn test_fair() {
+    assert_eq!(fair_main(), 101);
+}
+
+#[smol_potat::main(entry = false, threads = 3)]
+async fn worker_threads() -> usize {
+    SMOL_POTAT_THREADS
+}
+
+#[smol_potat::main(entry = false, threads = "auto")]
+async fn auto_worker_threads() -> usize {
+    SMOL_POTAT_THREADS
+}
+
+#[test]
+fn test_threads_binding() {
+    assert_eq!(worker_threads(), 3);
+    assert_eq!(
+        auto_worker_threads(),
+        std::cmp::max(smol_potat::__private::num_cpus::get(), 1)
+    );
+}
+
+#[smol_potat::test(threads = 4, max_concurrency = 2)]
+async fn test_max_concurrency() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let running = Arc::new(AtomicUsize::new(0));
+    let most = Arc::new(AtomicUsize::new(0));
+    let tasks: Vec<_> = (0..16)
+        .map(|_| {
+            let semaphore = semaphore.clone();
+            let running = running.clone();
+            let most = most.clone();
+            smol_potat::spawn(async move {
+                let _permit = semaphore.acquire().await;
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                async_io::Timer::after(std::time::Duration::from_millis(5)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await;
+    }
+    assert_eq!(most.load(Ordering::SeqCst), 2);
+}
+
+// A hand-written `#[test]` is deduplicated, and other attributes, including attribute macros,
+// are applied after it in their original order.
+#[smol_potat::test]
+#[test]
+#[tracing::instrument]
+#[should_panic(expected = "layered")]
+async fn test_attributes_layered() {
+    panic!("layered");
+}
+
+mod timeout_cancels {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    static DROPPED: AtomicBool = AtomicBool::new(false);
+
+    struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            // Dropped by the timeout itself, not by unwinding from its panic.
+            DROPPED.store(!std::thread::panicking(), Ordering::SeqCst);
+        }
+    }
+
+    #[smol_potat::test(timeout = 10)]
+    #[ignore = "times out on purpose, run by test_timeout_drops_future"]
+    async fn hangs() {
+        let _guard = Guard;
+        async_io::Timer::after(Duration::from_secs(60)).await;
+    }
+
+    #[test]
+    fn test_timeout_drops_future() {
+        let result = std::panic::catch_unwind(hangs);
+        assert!(result.is_err());
+        assert!(DROPPED.load(Ordering::SeqCst));
+    }
+}
+
+mod isolated {
+    use std::cell::Cell;
+
+    thread_local! {
+        static COUNTER: Cell<u32> = const { Cell::new(0) };
+    }
+
+    #[smol_potat::test(isolated)]
+    async fn fresh_thread_locals() {
+        assert_eq!(COUNTER.with(Cell::get), 0);
+        COUNTER.with(|counter| counter.set(1));
+    }
+
+    #[smol_potat::test(isolated)]
+    #[should_panic(expected = "isolated panic")]
+    async fn panics() {
+        panic!("isolated panic");
+    }
+
+    #[test]
+    fn test_isolated() {
+        COUNTER.with(|counter| counter.set(5));
+        fresh_thread_locals();
+        fresh_thread_locals();
+        assert_eq!(COUNTER.with(Cell::get), 5);
+    }
+}
+
+mod fixtures {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static TORN_DOWN: AtomicU32 = AtomicU32::new(0);
+
+    struct Database {
+        name: &'static str,
+    }
+
+    async fn start_database() -> Database {
+        smol::future::yield_now().await;
+        Database { name: "test" }
+    }
+
+    async fn stop_database() {
+        smol::future::yield_now().await;
+        TORN_DOWN.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[smol_potat::test(setup = start_database)]
+    async fn uses_fixture(db: Database) {
+        assert_eq!(db.name, "test");
+    }
+
+    #[smol_potat::test(setup = start_database, teardown = stop_database)]
+    #[ignore = "panics on purpose, run by test_teardown"]
+    async fn panics(db: Database) {
+        assert_eq!(db.name, "test");
+        panic!("boom");
+    }
+
+    #[test]
+    fn test_teardown() {
+        let result = std::panic::catch_unwind(panics);
+        assert!(result.is_err());
+        assert_eq!(TORN_DOWN.load(Ordering::SeqCst), 1);
+    }
+}
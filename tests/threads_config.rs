@@ -0,0 +1,34 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::process::Command;
+
+#[smol_potat::main(threads_config = "runtime.toml", threads = 1)]
+async fn main() -> Result<(), Box<dyn Error>> {
+    if let Some(threads) = env::var_os("SMOL_POTAT_CHILD") {
+        assert_eq!(
+            threads.to_str(),
+            Some(SMOL_POTAT_THREADS.to_string().as_str())
+        );
+        return Ok(());
+    }
+
+    let dir = env::temp_dir().join(format!("smol-potat-threads-config-{}", std::process::id()));
+    let configured = dir.join("configured");
+    let empty = dir.join("empty");
+    fs::create_dir_all(&configured)?;
+    fs::create_dir_all(&empty)?;
+    fs::write(configured.join("runtime.toml"), "threads = 3\n")?;
+
+    // The file takes precedence over `threads`, which is the fallback without a file.
+    for (cwd, threads) in [(&configured, "3"), (&empty, "1")] {
+        let status = Command::new(env::current_exe()?)
+            .current_dir(cwd)
+            .env("SMOL_POTAT_CHILD", threads)
+            .status()?;
+        assert!(status.success());
+    }
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
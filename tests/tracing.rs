@@ -0,0 +1,5 @@
+#[smol_potat::test(tracing)]
+async fn test_tracing() {
+    tracing::info!("running");
+    assert!(tracing::dispatcher::has_been_set());
+}
@@ -0,0 +1,13 @@
+#[test]
+#[cfg_attr(
+    feature = "clap",
+    ignore = "main takes parameters with the clap feature"
+)]
+#[cfg_attr(
+    not(any(feature = "async-signal", feature = "ctrlc-integration")),
+    ignore = "catch_signals is rejected without a signal feature"
+)]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,6 @@
+#[smol_potat::bench]
+fn my_bench() {
+    async_io::Timer::after(std::time::Duration::from_millis(1)).await;
+}
+
+fn main() {}
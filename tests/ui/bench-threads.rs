@@ -0,0 +1,4 @@
+#[smol_potat::bench(threads = 2, local)]
+async fn my_bench() {}
+
+fn main() {}
@@ -0,0 +1,2 @@
+#[smol_potat::main(bare, backoff_ms = 10)]
+async fn main() {}
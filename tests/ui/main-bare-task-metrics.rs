@@ -0,0 +1,2 @@
+#[smol_potat::main(bare, task_metrics)]
+async fn main() {}
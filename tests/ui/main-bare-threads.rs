@@ -0,0 +1,2 @@
+#[smol_potat::main(bare, threads = 2)]
+async fn main() {}
@@ -0,0 +1,4 @@
+#[smol_potat::main(catch_signals, exit_code)]
+async fn main() -> i32 {
+    0
+}
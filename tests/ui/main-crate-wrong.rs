@@ -0,0 +1,6 @@
+mod runtime {}
+
+#[smol_potat::main(crate = runtime, entry = false)]
+async fn run() {}
+
+fn main() {}
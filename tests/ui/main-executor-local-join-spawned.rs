@@ -0,0 +1,2 @@
+#[smol_potat::main(executor = "local", join_spawned)]
+async fn main() {}
@@ -0,0 +1,4 @@
+#[smol_potat::main(exit_code)]
+async fn main() -> u8 {
+    0
+}
@@ -0,0 +1,2 @@
+#[smol_potat::main(local, expose_executor)]
+async fn main() {}
@@ -0,0 +1,2 @@
+#[smol_potat::main(local, threads = 2)]
+async fn main() {}
@@ -0,0 +1,4 @@
+#[smol_potat::main]
+async fn main() -> ! {
+    loop {}
+}
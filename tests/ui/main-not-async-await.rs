@@ -0,0 +1,4 @@
+#[smol_potat::main]
+fn main() {
+    async_io::Timer::after(std::time::Duration::from_millis(1)).await;
+}
@@ -0,0 +1,2 @@
+#[smol_potat::main]
+fn main() {}
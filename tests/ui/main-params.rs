@@ -0,0 +1,2 @@
+#[smol_potat::main]
+async fn main(args: Vec<String>) {}
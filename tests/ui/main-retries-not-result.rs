@@ -0,0 +1,2 @@
+#[smol_potat::main(retries = 3)]
+async fn main() {}
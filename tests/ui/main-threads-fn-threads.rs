@@ -0,0 +1,6 @@
+fn cpu_quota() -> usize {
+    2
+}
+
+#[smol_potat::main(threads = 2, threads_fn = cpu_quota)]
+async fn main() {}
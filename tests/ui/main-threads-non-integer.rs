@@ -0,0 +1,6 @@
+fn thread_count() -> &'static str {
+    "4"
+}
+
+#[smol_potat::main(threads = thread_count())]
+async fn main() {}
@@ -0,0 +1,2 @@
+#[smol_potat::main(timeout = 1000)]
+async fn main() {}
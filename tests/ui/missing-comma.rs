@@ -0,0 +1,4 @@
+#[smol_potat::test(local timeout = 10)]
+async fn my_test() {}
+
+fn main() {}
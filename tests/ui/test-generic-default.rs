@@ -0,0 +1,4 @@
+#[smol_potat::test]
+async fn my_test<T>() {}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[smol_potat::test(matrix_threads = [2, 2])]
+async fn my_test() {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+#[smol_potat::test]
+fn my_test() {
+    async_io::Timer::after(std::time::Duration::from_millis(1)).await;
+}
+
+fn main() {}
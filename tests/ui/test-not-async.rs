@@ -0,0 +1,4 @@
+#[smol_potat::test]
+fn my_test() {}
+
+fn main() {}
@@ -0,0 +1,4 @@
+#[smol_potat::test(pretty_errors)]
+async fn my_test() {}
+
+fn main() {}
@@ -0,0 +1,6 @@
+#[smol_potat::test(report_errors)]
+async fn my_test() -> Result<(), String> {
+    Ok(())
+}
+
+fn main() {}
@@ -0,0 +1,2 @@
+#[smol_potat::main(threads = "all")]
+async fn main() {}
@@ -0,0 +1,2 @@
+#[smol_potat::main(threads = 10000000)]
+async fn main() {}
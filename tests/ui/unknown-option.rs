@@ -0,0 +1,2 @@
+#[smol_potat::main(thread = 2)]
+async fn main() {}
@@ -0,0 +1,20 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static WRAPPED: AtomicBool = AtomicBool::new(false);
+
+async fn record<F: Future>(future: F) -> F::Output {
+    WRAPPED.store(true, Ordering::SeqCst);
+    future.await
+}
+
+#[smol_potat::main(wrap = record)]
+async fn main() -> u8 {
+    42
+}
+
+#[test]
+fn test_wrap() {
+    assert_eq!(main(), 42);
+    assert!(WRAPPED.load(Ordering::SeqCst));
+}